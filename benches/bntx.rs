@@ -0,0 +1,70 @@
+use bntx::{BntxFile, BntxFileBuilder, SurfaceFormat};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::hint::black_box;
+use std::io::Cursor;
+
+fn bc7_data(size: usize, mipmap_count: u32) -> Vec<u8> {
+    let len: usize = (0..mipmap_count)
+        .map(|mip| {
+            let blocks = (size >> mip).max(1).div_ceil(4);
+            blocks * blocks * 16
+        })
+        .sum();
+    (0..len).map(|i| i as u8).collect()
+}
+
+fn write_bytes(bntx: &BntxFile) -> Vec<u8> {
+    let mut writer = Cursor::new(Vec::with_capacity(bntx.size_on_disk().unwrap()));
+    bntx.write(&mut writer).unwrap();
+    writer.into_inner()
+}
+
+fn bench_texture(c: &mut Criterion, name: &str, bntx: &BntxFile, data: &[u8], size: u32) {
+    let bytes = write_bytes(bntx);
+
+    c.bench_function(&format!("from_image_data {name}"), |b| {
+        b.iter(|| {
+            BntxFileBuilder::new("tex", size, size, SurfaceFormat::BC7Srgb, black_box(data))
+                .mipmap_count(bntx.num_mipmaps())
+                .build()
+                .unwrap()
+        })
+    });
+
+    c.bench_function(&format!("read {name}"), |b| {
+        b.iter(|| BntxFile::read_le(&mut Cursor::new(black_box(&bytes))).unwrap())
+    });
+
+    // Read a new file for each iteration to avoid using the cached data.
+    c.bench_function(&format!("deswizzled_data {name}"), |b| {
+        b.iter_batched(
+            || BntxFile::read_le(&mut Cursor::new(&bytes)).unwrap(),
+            |bntx| bntx.deswizzled_data().unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function(&format!("write {name}"), |b| {
+        b.iter(|| write_bytes(black_box(bntx)))
+    });
+}
+
+fn bc7_2048(c: &mut Criterion) {
+    let data = bc7_data(2048, 12);
+    let bntx = BntxFileBuilder::new("tex", 2048, 2048, SurfaceFormat::BC7Srgb, &data)
+        .mipmap_count(12)
+        .build()
+        .unwrap();
+    bench_texture(c, "bc7 2048x2048", &bntx, &data, 2048);
+}
+
+fn bc7_1x1(c: &mut Criterion) {
+    let data = bc7_data(1, 1);
+    let bntx = BntxFileBuilder::new("tex", 1, 1, SurfaceFormat::BC7Srgb, &data)
+        .build()
+        .unwrap();
+    bench_texture(c, "bc7 1x1", &bntx, &data, 1);
+}
+
+criterion_group!(benches, bc7_2048, bc7_1x1);
+criterion_main!(benches);
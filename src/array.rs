@@ -0,0 +1,32 @@
+use ndarray::ArrayView3;
+
+use crate::{BntxError, BntxFile, SurfaceFormat};
+
+impl BntxFile {
+    /// Create a [BntxFile] with a single mipmap from an image with shape `[height, width, channels]`.
+    ///
+    /// Images with 1 or 2 channels use [SurfaceFormat::R8Unorm] or [SurfaceFormat::R8G8Unorm].
+    /// Images with 3 or 4 channels use [SurfaceFormat::R8G8B8A8Srgb]
+    /// with alpha set to 255 for images without alpha.
+    /// Returns [BntxError::UnsupportedChannelCount] for any other number of channels.
+    pub fn from_array_view(name: &str, image: ArrayView3<u8>) -> Result<Self, BntxError> {
+        let (height, width, channels) = image.dim();
+
+        let (format, data): (_, Vec<u8>) = match channels {
+            1 => (SurfaceFormat::R8Unorm, image.iter().copied().collect()),
+            2 => (SurfaceFormat::R8G8Unorm, image.iter().copied().collect()),
+            3 => (
+                SurfaceFormat::R8G8B8A8Srgb,
+                image
+                    .rows()
+                    .into_iter()
+                    .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255u8])
+                    .collect(),
+            ),
+            4 => (SurfaceFormat::R8G8B8A8Srgb, image.iter().copied().collect()),
+            _ => return Err(BntxError::UnsupportedChannelCount(channels)),
+        };
+
+        Self::from_image_data(name, width as u32, height as u32, 1, 1, 1, format, &data)
+    }
+}
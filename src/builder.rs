@@ -0,0 +1,509 @@
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use tegra_swizzle::block_height_mip0;
+use tegra_swizzle::div_round_up;
+use tegra_swizzle::surface::{swizzle_surface, swizzled_surface_size, BlockDim};
+use tegra_swizzle::BlockHeight;
+
+use crate::dict::DictBuilder;
+use crate::{
+    calculate_mipmap_offsets, check_data_offsets, max_mipmap_count, size_before_brtd,
+    surface_alignment, u32_offset, BntxError, BntxFile, BntxHeader, BntxStr, Brtd, Brti, ByteOrder,
+    ChannelHint, HeaderInner, Mipmaps, NxHeader, RelocationTable, StrSection, SurfaceFormat,
+    TextureDimension, TextureViewDimension, BNTX_HEADER_SIZE, BRTD_HEADER_SIZE, BRTD_SECTION_START,
+    MEM_POOL_ADDRESS,
+};
+
+const DEFAULT_TEXTURE_NAME: &str = "texture";
+
+/// The arrangement of the image data passed to [BntxFileBuilder].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataLayout {
+    /// Unswizzled data with rows of pixels or blocks stored in order.
+    Linear,
+    /// Data already swizzled using the block height inferred from the dimensions.
+    Swizzled,
+}
+
+/// The color space of the image data passed to [BntxFileBuilder].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Values are linear and will be gamma encoded for sRGB formats.
+    Linear,
+    /// Values are already gamma encoded for sRGB formats.
+    Srgb,
+}
+
+/// Settings for creating a [BntxFile] from unswizzled image data.
+///
+/// # Examples
+/**
+```rust
+use bntx::{BntxFileBuilder, SurfaceFormat};
+
+let data = vec![0u8; 4 * 4 * 4];
+let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Srgb, &data)
+    .build()
+    .unwrap();
+```
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct BntxFileBuilder<'a> {
+    name: Option<&'a str>,
+    width: u32,
+    height: u32,
+    depth: u32,
+    mipmap_count: u32,
+    layer_count: u32,
+    format: SurfaceFormat,
+    data: &'a [u8],
+    swizzle: u16,
+    unk2: u32,
+    parent_offset: u64,
+    version: (u16, u16),
+    multi_sample_count: u32,
+    flags: u8,
+    brtd_offset: usize,
+    strict: bool,
+    data_layout: DataLayout,
+    color_space: ColorSpace,
+    trim_trailing_zeros: bool,
+}
+
+impl<'a> BntxFileBuilder<'a> {
+    /// Settings for a 2D texture with a single mipmap and array layer.
+    /// Textures without a `name` use the name `"texture"`.
+    pub fn new(
+        name: impl Into<Option<&'a str>>,
+        width: u32,
+        height: u32,
+        format: SurfaceFormat,
+        data: &'a [u8],
+    ) -> Self {
+        Self {
+            name: name.into(),
+            width,
+            height,
+            depth: 1,
+            mipmap_count: 1,
+            layer_count: 1,
+            format,
+            data,
+            swizzle: 0,
+            unk2: 32,
+            parent_offset: BNTX_HEADER_SIZE as u64,
+            version: (0, 4),
+            multi_sample_count: 1,
+            flags: 1,
+            brtd_offset: BRTD_SECTION_START,
+            strict: false,
+            data_layout: DataLayout::Linear,
+            color_space: ColorSpace::Srgb,
+            trim_trailing_zeros: false,
+        }
+    }
+
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn mipmap_count(mut self, mipmap_count: u32) -> Self {
+        self.mipmap_count = mipmap_count;
+        self
+    }
+
+    /// The number of array layers. A `layer_count` of 0 uses a single layer.
+    ///
+    /// 3D textures always have a single layer with the Z extent set using [Self::depth].
+    pub fn layer_count(mut self, layer_count: u32) -> Self {
+        self.layer_count = layer_count;
+        self
+    }
+
+    /// The value for the BRTI swizzle field. This is usually 0.
+    ///
+    /// Some array textures from games use non-zero values.
+    /// The meaning of these values isn't known, so they are written as is
+    /// to allow reconstructing the original file.
+    pub fn swizzle(mut self, swizzle: u16) -> Self {
+        self.swizzle = swizzle;
+        self
+    }
+
+    /// The value for the unknown BRTI field following the format. This is usually 32.
+    pub fn unk2(mut self, unk2: u32) -> Self {
+        self.unk2 = unk2;
+        self
+    }
+
+    /// The offset of the NX header written to the BRTI parent pointer.
+    /// This defaults to the NX header offset immediately following the BNTX header.
+    ///
+    /// Only change this when embedding the texture in a container
+    /// that places the NX header at a different position.
+    pub fn parent_offset(mut self, offset: u64) -> Self {
+        self.parent_offset = offset;
+        self
+    }
+
+    /// The file format version written to the BNTX header.
+    /// This defaults to `(0, 4)` used by most Switch games.
+    pub fn format_version(mut self, major: u16, minor: u16) -> Self {
+        self.version = (major, minor);
+        self
+    }
+
+    /// The number of samples per pixel for multisampled render targets. This defaults to 1.
+    ///
+    /// The image data only stores the descriptor's dimensions.
+    /// The sample data for multisampled textures is only created on the GPU.
+    pub fn multi_sample_count(mut self, count: u32) -> Self {
+        self.multi_sample_count = count;
+        self
+    }
+
+    /// The value for the BRTI flags field. This is usually 1.
+    /// Some game files use 0, which may affect how the texture memory is allocated.
+    pub fn flags(mut self, flags: u8) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// The position of the BRTD section containing the image data.
+    /// This defaults to `0xFF0` so that the image data starts at `0x1000`.
+    ///
+    /// Some game files use a different position to reserve more space for the other sections.
+    pub fn brtd_offset(mut self, offset: usize) -> Self {
+        self.brtd_offset = offset;
+        self
+    }
+
+    /// Check block compressed data for blocks containing only zeros.
+    /// This is disabled by default.
+    ///
+    /// All zero blocks are valid but usually indicate that uncompressed or empty data
+    /// was passed for a compressed format.
+    /// If more than half of the blocks are zero, [BntxFileBuilder::build]
+    /// returns [BntxError::SuspectPixelData].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The arrangement of the image data. This defaults to [DataLayout::Linear].
+    ///
+    /// [DataLayout::Swizzled] data is used as is without swizzling.
+    /// The data length must match the swizzled size for the texture,
+    /// or [BntxFileBuilder::build] returns [BntxError::DataSizeMismatch].
+    pub fn data_layout(mut self, data_layout: DataLayout) -> Self {
+        self.data_layout = data_layout;
+        self
+    }
+
+    /// The color space of the image data. This defaults to [ColorSpace::Srgb].
+    ///
+    /// [ColorSpace::Linear] data is gamma encoded if the format is sRGB.
+    /// The alpha channel is not modified.
+    /// Data for other formats is stored without any conversion.
+    /// Only uncompressed formats can be converted,
+    /// so [BntxFileBuilder::build] returns [BntxError::UnsupportedOperation]
+    /// for linear data with compressed sRGB formats.
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Remove trailing zero bytes from the swizzled data to reduce the file size.
+    /// This is disabled by default.
+    ///
+    /// The BRTI image size still includes the removed bytes,
+    /// and [BntxFile::deswizzled_data] treats the missing bytes as zeros.
+    /// Other tools may expect the full swizzled data.
+    pub fn trim_trailing_zeros(mut self, trim_trailing_zeros: bool) -> Self {
+        self.trim_trailing_zeros = trim_trailing_zeros;
+        self
+    }
+
+    /// Swizzles the image data and creates the [BntxFile].
+    ///
+    /// Returns [BntxError::IncompatibleDimensionForFormat] for 3D textures
+    /// using a `format` that does not support a `depth` greater than 1.
+    /// Returns [BntxError::TooManyMipmaps] if `mipmap_count` exceeds the full mipmap chain.
+    ///
+    /// A `mipmap_count` of 0 creates a texture without any image data.
+    pub fn build(&self) -> Result<BntxFile, BntxError> {
+        let Self {
+            name,
+            width,
+            height,
+            depth,
+            mipmap_count,
+            layer_count,
+            format,
+            data,
+            swizzle,
+            unk2,
+            parent_offset,
+            version,
+            multi_sample_count,
+            flags,
+            brtd_offset,
+            strict,
+            data_layout,
+            color_space,
+            trim_trailing_zeros,
+        } = *self;
+
+        let name = name.unwrap_or(DEFAULT_TEXTURE_NAME);
+
+        // Names are written as UTF-8, which may not be supported by the game for non-ASCII text.
+        if !name.is_ascii() {
+            return Err(BntxError::NonAsciiName(name.to_owned()));
+        }
+
+        // The Z extent of a 3D texture is the depth rather than the layer count.
+        let layer_count = layer_count.max(1);
+        if layer_count > 1 && depth > 1 {
+            return Err(BntxError::AmbiguousLayerDepth { layer_count, depth });
+        }
+
+        if depth > 1 && !format.supports_3d() {
+            return Err(BntxError::IncompatibleDimensionForFormat { format, depth });
+        }
+
+        let max_mipmaps = max_mipmap_count(width, height, depth);
+        if mipmap_count > max_mipmaps {
+            return Err(BntxError::TooManyMipmaps {
+                requested: mipmap_count,
+                max: max_mipmaps,
+            });
+        }
+
+        let block_dim = format.block_dim();
+        let bytes_per_pixel = format.bytes_per_pixel();
+
+        if strict && block_dim != BlockDim::uncompressed() {
+            let total_blocks = data.len() / bytes_per_pixel;
+            let zero_blocks = data
+                .chunks_exact(bytes_per_pixel)
+                .filter(|block| block.iter().all(|b| *b == 0))
+                .count();
+            if zero_blocks * 2 > total_blocks {
+                return Err(BntxError::SuspectPixelData {
+                    zero_blocks,
+                    total_blocks,
+                });
+            }
+        }
+
+        let data = if color_space == ColorSpace::Linear && format.is_srgb() {
+            if data_layout == DataLayout::Swizzled || block_dim != BlockDim::uncompressed() {
+                return Err(BntxError::UnsupportedOperation {
+                    operation: "gamma correction",
+                    format,
+                });
+            }
+            Cow::Owned(linear_rgba8_to_srgb(data))
+        } else {
+            Cow::Borrowed(data)
+        };
+
+        // Let tegra_swizzle calculate the block height.
+        // This matches the value inferred for missing block heights like in nutexb.
+        let block_height = block_height_mip0(div_round_up(height as usize, block_dim.height.get()));
+
+        let block_height_log2 = match block_height {
+            BlockHeight::One => 0,
+            BlockHeight::Two => 1,
+            BlockHeight::Four => 2,
+            BlockHeight::Eight => 3,
+            BlockHeight::Sixteen => 4,
+            BlockHeight::ThirtyTwo => 5,
+        };
+
+        // Some files from games have no mipmaps and no image data.
+        let data = if mipmap_count == 0 {
+            Vec::new()
+        } else if data_layout == DataLayout::Swizzled {
+            let expected = swizzled_surface_size(
+                width as usize,
+                height as usize,
+                depth as usize,
+                block_dim,
+                Some(block_height),
+                bytes_per_pixel,
+                mipmap_count as usize,
+                layer_count as usize,
+            );
+            if data.len() != expected {
+                return Err(BntxError::DataSizeMismatch {
+                    expected,
+                    actual: data.len(),
+                });
+            }
+            data.into_owned()
+        } else {
+            swizzle_surface(
+                width as usize,
+                height as usize,
+                depth as usize,
+                &data,
+                block_dim,
+                Some(block_height),
+                bytes_per_pixel,
+                mipmap_count as usize,
+                layer_count as usize,
+            )
+            .map_err(|source| BntxError::SwizzleSurface {
+                width,
+                height,
+                depth,
+                format,
+                mipmap_count,
+                layer_count,
+                source,
+            })?
+        };
+
+        // Offsets in the file and relocation table are stored as u32.
+        let texture_data_start =
+            brtd_offset
+                .checked_add(BRTD_HEADER_SIZE)
+                .ok_or(BntxError::OffsetOverflow {
+                    offset: brtd_offset,
+                })?;
+        check_data_offsets(texture_data_start, data.len())?;
+
+        let image_size = u32_offset(data.len())?;
+        let mut data = data;
+        if trim_trailing_zeros {
+            let len = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            data.truncate(len);
+        }
+
+        let mut str_section = StrSection {
+            block_size: 0,
+            block_offset: 0,
+            strings: vec![BntxStr::from(name.to_owned())],
+        };
+
+        let str_section_size = str_section.get_size();
+        let mut dict_builder = DictBuilder::new();
+        dict_builder.insert(name);
+        let dict = dict_builder.build();
+        let dict_section_size = dict.get_size();
+
+        // The dictionary isn't a separate block,
+        // so the next block after the string section is the BRTI.
+        str_section.block_size = u32_offset(str_section_size + dict_section_size)?;
+        str_section.block_offset = (str_section_size + dict_section_size) as u64;
+
+        let size = size_before_brtd(str_section_size, dict_section_size, mipmap_count as usize);
+        if size > brtd_offset {
+            return Err(BntxError::HeaderTooLarge {
+                size,
+                max: brtd_offset,
+            });
+        }
+
+        let texture_view_dimension = match (multi_sample_count > 1, layer_count > 1) {
+            (true, true) => TextureViewDimension::D2MultisampleArray,
+            (true, false) => TextureViewDimension::D2Multisample,
+            (false, _) => TextureViewDimension::from_layers_depth(layer_count, depth),
+        };
+
+        let mipmap_offsets = calculate_mipmap_offsets(
+            texture_data_start,
+            mipmap_count,
+            width,
+            block_dim,
+            height,
+            depth,
+            block_height,
+            bytes_per_pixel,
+        );
+
+        let mut bntx = BntxFile {
+            header: BntxHeader {
+                version,
+                bom: ByteOrder::LittleEndian,
+                inner: HeaderInner {
+                    revision: 0x400c,
+                    file_name: name.into(),
+                    str_section,
+                    // The relocation table depends on the positions of the other sections.
+                    reloc_table: RelocationTable {
+                        sections: Vec::new(),
+                        entries: Vec::new(),
+                    },
+                },
+            },
+            nx_header: NxHeader {
+                count: 1,
+                dict,
+                dict_size: MEM_POOL_ADDRESS,
+                brti: Brti {
+                    size: 3576,
+                    size2: 3576,
+                    flags,
+                    texture_dimension: if depth > 1 {
+                        TextureDimension::D3
+                    } else {
+                        TextureDimension::D2
+                    },
+                    tile_mode: 0,
+                    swizzle,
+                    // The mipmap count is at most the full mipmap chain for u32 dimensions.
+                    mipmap_count: mipmap_count as u16,
+                    multi_sample_count,
+                    format,
+                    unk2,
+                    width,
+                    height,
+                    depth,
+                    layer_count,
+                    block_height_log2,
+                    unk4: [65543, 0, 0, 0, 0, 0],
+                    image_size,
+                    align: surface_alignment(block_height),
+                    comp_sel: ChannelHint::Rgba.comp_sel(),
+                    texture_view_dimension,
+                    name_addr: name.to_owned().into(),
+                    parent_addr: parent_offset,
+                    mipmaps: Mipmaps { mipmap_offsets },
+                    unk5: 0,
+                    unk6: 0,
+                    unk7: 0,
+                },
+                brtd: Brtd { image_data: data },
+            },
+            deswizzled_data: OnceLock::new(),
+        };
+        bntx.header.inner.reloc_table = bntx.compute_relocation_table()?;
+        Ok(bntx)
+    }
+}
+
+fn linear_rgba8_to_srgb(data: &[u8]) -> Vec<u8> {
+    let encode = |x: u8| {
+        let linear = x as f32 / 255.0;
+        let srgb = if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    };
+
+    // The alpha channel is always linear.
+    data.chunks(4)
+        .flat_map(|pixel| {
+            pixel
+                .iter()
+                .enumerate()
+                .map(move |(i, x)| if i < 3 { encode(*x) } else { *x })
+        })
+        .collect()
+}
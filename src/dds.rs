@@ -1,26 +1,39 @@
 use ddsfile::{
     AlphaMode, Caps2, D3D10ResourceDimension, D3DFormat, Dds, DxgiFormat, FourCC, NewDxgiParams,
 };
+use std::borrow::Cow;
+use tegra_swizzle::surface::swizzle_surface;
 use thiserror::Error;
 
-use crate::{BntxFile, SurfaceFormat};
+use crate::{BntxError, BntxFile, SurfaceFormat};
 
 #[derive(Debug, Error)]
 pub enum CreateBntxError {
-    #[error("failed to swizzle surface")]
-    SwizzleError(#[from] tegra_swizzle::SwizzleError),
+    #[error("failed to create BNTX")]
+    BntxError(#[from] crate::BntxError),
     #[error("the given DDS format is not supported")]
     UnsupportedImageFormat,
 }
 
-pub fn create_dds(bntx: &BntxFile) -> Result<Dds, tegra_swizzle::SwizzleError> {
+/// Creates a DDS file with the deswizzled image data from `bntx`.
+///
+/// Returns [BntxError::UnsupportedOperation] for formats without a DXGI equivalent like ASTC.
+pub fn create_dds(bntx: &BntxFile) -> Result<Dds, BntxError> {
     let some_if_above_one = |x| if x > 0 { Some(x) } else { None };
 
+    let format = bntx.nx_header.brti.format;
+    let dxgi_format = format
+        .try_into_dxgi()
+        .ok_or(BntxError::UnsupportedOperation {
+            operation: "DDS export",
+            format,
+        })?;
+
     let mut dds = Dds::new_dxgi(NewDxgiParams {
         height: bntx.nx_header.brti.height,
         width: bntx.nx_header.brti.width,
         depth: some_if_above_one(bntx.nx_header.brti.depth),
-        format: bntx.nx_header.brti.format.into(),
+        format: dxgi_format,
         mipmap_levels: some_if_above_one(bntx.nx_header.brti.mipmap_count as u32),
         array_layers: some_if_above_one(bntx.nx_header.brti.layer_count),
         caps2: if bntx.nx_header.brti.depth > 1 {
@@ -35,9 +48,8 @@ pub fn create_dds(bntx: &BntxFile) -> Result<Dds, tegra_swizzle::SwizzleError> {
         } else {
             D3D10ResourceDimension::Texture2D
         },
-        alpha_mode: AlphaMode::Unknown, // TODO: Alpha mode?
-    })
-    .unwrap();
+        alpha_mode: format.preferred_alpha_mode(),
+    })?;
 
     // DDS stores mipmaps in a contiguous region of memory.
     dds.data = bntx.deswizzled_data()?;
@@ -47,6 +59,8 @@ pub fn create_dds(bntx: &BntxFile) -> Result<Dds, tegra_swizzle::SwizzleError> {
 
 // TODO: Make this a method?
 pub fn create_bntx(name: &str, dds: &Dds) -> Result<BntxFile, CreateBntxError> {
+    let format = dds_image_format(dds).ok_or(CreateBntxError::UnsupportedImageFormat)?;
+
     BntxFile::from_image_data(
         name,
         dds.get_width(),
@@ -54,12 +68,75 @@ pub fn create_bntx(name: &str, dds: &Dds) -> Result<BntxFile, CreateBntxError> {
         dds.get_depth(),
         dds.get_num_mipmap_levels(),
         layer_count(dds),
-        dds_image_format(dds).ok_or(CreateBntxError::UnsupportedImageFormat)?,
-        &dds.data,
+        format,
+        &dds_image_data(dds),
     )
     .map_err(Into::into)
 }
 
+impl BntxFile {
+    /// Swizzles the image data from `dds` and replaces the existing image data.
+    /// All other header fields are preserved.
+    ///
+    /// The format, dimensions, mipmaps, and array layers of `dds` must match the existing texture.
+    pub fn replace_swizzled_data_from_dds(&mut self, dds: &Dds) -> Result<(), BntxError> {
+        let format = dds_image_format(dds);
+        if format != Some(self.image_format()) {
+            return Err(BntxError::IncompatibleFormat {
+                expected: self.image_format(),
+                actual: format,
+            });
+        }
+
+        for (field, expected, actual) in [
+            ("width", self.width(), dds.get_width()),
+            ("height", self.height(), dds.get_height()),
+            ("depth", self.depth(), dds.get_depth()),
+            (
+                "mipmap count",
+                self.num_mipmaps(),
+                dds.get_num_mipmap_levels(),
+            ),
+            ("layer count", self.num_array_layers(), layer_count(dds)),
+        ] {
+            if expected != actual {
+                return Err(BntxError::DimensionMismatch {
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        let data = swizzle_surface(
+            self.width() as usize,
+            self.height() as usize,
+            self.depth() as usize,
+            &dds_image_data(dds),
+            self.image_format().block_dim(),
+            Some(self.block_height_mip0()),
+            self.image_format().bytes_per_pixel(),
+            self.num_mipmaps() as usize,
+            self.num_array_layers() as usize,
+        )?;
+
+        self.set_swizzled_data(data)
+    }
+}
+
+fn dds_image_data(dds: &Dds) -> Cow<'_, [u8]> {
+    // The unused X channel may contain any value, so treat the image as fully opaque.
+    if dds.get_d3d_format() == Some(D3DFormat::X8R8G8B8) {
+        let mut data = dds.data.clone();
+        for pixel in data.chunks_exact_mut(4) {
+            pixel[3] = 255u8;
+        }
+        Cow::Owned(data)
+    } else {
+        Cow::Borrowed(&dds.data)
+    }
+}
+
 fn layer_count(dds: &Dds) -> u32 {
     // Array layers for DDS are calculated differently for cube maps.
     if matches!(&dds.header10, Some(header10) if header10.misc_flag == ddsfile::MiscFlag::TEXTURECUBE)
@@ -81,12 +158,30 @@ fn dds_image_format(dds: &Dds) -> Option<SurfaceFormat> {
         .or_else(|| fourcc.and_then(image_format_from_fourcc))
 }
 
-fn image_format_from_dxgi(format: DxgiFormat) -> Option<SurfaceFormat> {
+/// Returns the equivalent [SurfaceFormat] for `format`
+/// or [None] if `format` can't be represented in a BNTX file.
+pub fn image_format_from_dxgi(format: DxgiFormat) -> Option<SurfaceFormat> {
     match format {
         DxgiFormat::R8_UNorm => Some(SurfaceFormat::R8Unorm),
+        DxgiFormat::R32_Float => Some(SurfaceFormat::R32Sfloat),
+        DxgiFormat::R32_UInt => Some(SurfaceFormat::R32Uint),
+        DxgiFormat::R32_SInt => Some(SurfaceFormat::R32Sint),
+        DxgiFormat::R16_UNorm => Some(SurfaceFormat::R16Unorm),
+        DxgiFormat::R16_SNorm => Some(SurfaceFormat::R16Snorm),
+        DxgiFormat::R16_Float => Some(SurfaceFormat::R16Sfloat),
+        DxgiFormat::R8G8_UNorm => Some(SurfaceFormat::R8G8Unorm),
+        DxgiFormat::R8G8_SNorm => Some(SurfaceFormat::R8G8Snorm),
+        DxgiFormat::R16G16_UNorm => Some(SurfaceFormat::R16G16Unorm),
+        DxgiFormat::R16G16_SNorm => Some(SurfaceFormat::R16G16Snorm),
+        DxgiFormat::R16G16_Float => Some(SurfaceFormat::R16G16Sfloat),
+        DxgiFormat::R8G8B8A8_UNorm => Some(SurfaceFormat::R8G8B8A8Unorm),
         DxgiFormat::R8G8B8A8_UNorm_sRGB => Some(SurfaceFormat::R8G8B8A8Srgb),
         DxgiFormat::B8G8R8A8_UNorm => Some(SurfaceFormat::B8G8R8A8Unorm),
         DxgiFormat::B8G8R8A8_UNorm_sRGB => Some(SurfaceFormat::B8G8R8A8Srgb),
+        DxgiFormat::R16G16B16A16_UNorm => Some(SurfaceFormat::R16G16B16A16Unorm),
+        DxgiFormat::R16G16B16A16_SNorm => Some(SurfaceFormat::R16G16B16A16Snorm),
+        DxgiFormat::R16G16B16A16_Float => Some(SurfaceFormat::R16G16B16A16Sfloat),
+        DxgiFormat::R10G10B10A2_UNorm => Some(SurfaceFormat::R10G10B10A2Unorm),
         DxgiFormat::BC1_UNorm => Some(SurfaceFormat::BC1Unorm),
         DxgiFormat::BC1_UNorm_sRGB => Some(SurfaceFormat::BC1Srgb),
         DxgiFormat::BC2_UNorm => Some(SurfaceFormat::BC2Unorm),
@@ -113,6 +208,10 @@ fn image_format_from_d3d(format: D3DFormat) -> Option<SurfaceFormat> {
         D3DFormat::DXT3 => Some(SurfaceFormat::BC2Unorm),
         D3DFormat::DXT4 => Some(SurfaceFormat::BC3Unorm),
         D3DFormat::DXT5 => Some(SurfaceFormat::BC3Unorm),
+        // The X channel is stored like alpha but the image should be treated as opaque.
+        D3DFormat::X8R8G8B8 => Some(SurfaceFormat::B8G8R8A8Unorm),
+        D3DFormat::A16B16G16R16 => Some(SurfaceFormat::R16G16B16A16Unorm),
+        D3DFormat::A16B16G16R16F => Some(SurfaceFormat::R16G16B16A16Sfloat),
         _ => None,
     }
 }
@@ -135,28 +234,172 @@ fn image_format_from_fourcc(fourcc: &FourCC) -> Option<SurfaceFormat> {
     }
 }
 
+impl SurfaceFormat {
+    /// The DDS alpha mode for how the alpha channel of this format is usually interpreted.
+    /// Formats without an alpha channel use [AlphaMode::Opaque].
+    ///
+    /// Premultiplied alpha can't be detected from the format,
+    /// so formats with alpha always use [AlphaMode::Straight].
+    pub fn preferred_alpha_mode(&self) -> AlphaMode {
+        match self {
+            SurfaceFormat::R8Unorm
+            | SurfaceFormat::R32Sfloat
+            | SurfaceFormat::R32Uint
+            | SurfaceFormat::R32Sint
+            | SurfaceFormat::R16Unorm
+            | SurfaceFormat::R16Snorm
+            | SurfaceFormat::R16Sfloat
+            | SurfaceFormat::R8G8Unorm
+            | SurfaceFormat::R8G8Snorm
+            | SurfaceFormat::R16G16Unorm
+            | SurfaceFormat::R16G16Snorm
+            | SurfaceFormat::R16G16Sfloat
+            | SurfaceFormat::BC1Unorm
+            | SurfaceFormat::BC1Srgb
+            | SurfaceFormat::BC4Unorm
+            | SurfaceFormat::BC4Snorm
+            | SurfaceFormat::BC5Unorm
+            | SurfaceFormat::BC5Snorm
+            | SurfaceFormat::BC6Sfloat
+            | SurfaceFormat::BC6Ufloat
+            | SurfaceFormat::Etc2Rgb8Unorm
+            | SurfaceFormat::EacR11Unorm => AlphaMode::Opaque,
+            SurfaceFormat::R8G8B8A8Unorm
+            | SurfaceFormat::R8G8B8A8Srgb
+            | SurfaceFormat::B8G8R8A8Unorm
+            | SurfaceFormat::B8G8R8A8Srgb
+            | SurfaceFormat::R16G16B16A16Unorm
+            | SurfaceFormat::R16G16B16A16Snorm
+            | SurfaceFormat::R16G16B16A16Sfloat
+            | SurfaceFormat::R10G10B10A2Unorm
+            | SurfaceFormat::BC2Unorm
+            | SurfaceFormat::BC2Srgb
+            | SurfaceFormat::BC3Unorm
+            | SurfaceFormat::BC3Srgb
+            | SurfaceFormat::BC7Unorm
+            | SurfaceFormat::BC7Srgb
+            | SurfaceFormat::Astc4x4Unorm
+            | SurfaceFormat::Astc4x4Srgb
+            | SurfaceFormat::Astc6x6Unorm
+            | SurfaceFormat::Astc6x6Srgb
+            | SurfaceFormat::Astc8x8Unorm
+            | SurfaceFormat::Astc8x8Srgb => AlphaMode::Straight,
+        }
+    }
+
+    /// The equivalent DXGI format or `None` if DXGI has no equivalent format like for ASTC or ETC2.
+    pub fn try_into_dxgi(&self) -> Option<DxgiFormat> {
+        match self {
+            SurfaceFormat::R8Unorm => Some(DxgiFormat::R8_UNorm),
+            SurfaceFormat::R32Sfloat => Some(DxgiFormat::R32_Float),
+            SurfaceFormat::R32Uint => Some(DxgiFormat::R32_UInt),
+            SurfaceFormat::R32Sint => Some(DxgiFormat::R32_SInt),
+            SurfaceFormat::R16Unorm => Some(DxgiFormat::R16_UNorm),
+            SurfaceFormat::R16Snorm => Some(DxgiFormat::R16_SNorm),
+            SurfaceFormat::R16Sfloat => Some(DxgiFormat::R16_Float),
+            SurfaceFormat::R8G8Unorm => Some(DxgiFormat::R8G8_UNorm),
+            SurfaceFormat::R8G8Snorm => Some(DxgiFormat::R8G8_SNorm),
+            SurfaceFormat::R16G16Unorm => Some(DxgiFormat::R16G16_UNorm),
+            SurfaceFormat::R16G16Snorm => Some(DxgiFormat::R16G16_SNorm),
+            SurfaceFormat::R16G16Sfloat => Some(DxgiFormat::R16G16_Float),
+            SurfaceFormat::R8G8B8A8Unorm => Some(DxgiFormat::R8G8B8A8_UNorm),
+            SurfaceFormat::R8G8B8A8Srgb => Some(DxgiFormat::R8G8B8A8_UNorm_sRGB),
+            SurfaceFormat::B8G8R8A8Unorm => Some(DxgiFormat::B8G8R8A8_UNorm),
+            SurfaceFormat::B8G8R8A8Srgb => Some(DxgiFormat::B8G8R8A8_UNorm_sRGB),
+            SurfaceFormat::R16G16B16A16Unorm => Some(DxgiFormat::R16G16B16A16_UNorm),
+            SurfaceFormat::R16G16B16A16Snorm => Some(DxgiFormat::R16G16B16A16_SNorm),
+            SurfaceFormat::R16G16B16A16Sfloat => Some(DxgiFormat::R16G16B16A16_Float),
+            SurfaceFormat::R10G10B10A2Unorm => Some(DxgiFormat::R10G10B10A2_UNorm),
+            SurfaceFormat::BC1Unorm => Some(DxgiFormat::BC1_UNorm),
+            SurfaceFormat::BC1Srgb => Some(DxgiFormat::BC1_UNorm_sRGB),
+            SurfaceFormat::BC2Unorm => Some(DxgiFormat::BC2_UNorm),
+            SurfaceFormat::BC2Srgb => Some(DxgiFormat::BC2_UNorm_sRGB),
+            SurfaceFormat::BC3Unorm => Some(DxgiFormat::BC3_UNorm),
+            SurfaceFormat::BC3Srgb => Some(DxgiFormat::BC3_UNorm_sRGB),
+            SurfaceFormat::BC4Unorm => Some(DxgiFormat::BC4_UNorm),
+            SurfaceFormat::BC4Snorm => Some(DxgiFormat::BC4_SNorm),
+            SurfaceFormat::BC5Unorm => Some(DxgiFormat::BC5_UNorm),
+            SurfaceFormat::BC5Snorm => Some(DxgiFormat::BC5_SNorm),
+            SurfaceFormat::BC6Sfloat => Some(DxgiFormat::BC6H_SF16),
+            SurfaceFormat::BC6Ufloat => Some(DxgiFormat::BC6H_UF16),
+            SurfaceFormat::BC7Unorm => Some(DxgiFormat::BC7_UNorm),
+            SurfaceFormat::BC7Srgb => Some(DxgiFormat::BC7_UNorm_sRGB),
+            // DXGI has no ASTC, ETC2, or EAC formats.
+            SurfaceFormat::Astc4x4Unorm
+            | SurfaceFormat::Astc4x4Srgb
+            | SurfaceFormat::Astc6x6Unorm
+            | SurfaceFormat::Astc6x6Srgb
+            | SurfaceFormat::Astc8x8Unorm
+            | SurfaceFormat::Astc8x8Srgb
+            | SurfaceFormat::Etc2Rgb8Unorm
+            | SurfaceFormat::EacR11Unorm => None,
+        }
+    }
+}
+
+/// Converts to [DxgiFormat::Unknown] for formats without a DXGI equivalent.
+/// Use [SurfaceFormat::try_into_dxgi] to handle these formats explicitly.
 impl From<SurfaceFormat> for DxgiFormat {
     fn from(f: SurfaceFormat) -> Self {
-        match f {
-            SurfaceFormat::R8Unorm => Self::R8_UNorm,
-            SurfaceFormat::R8G8B8A8Unorm => Self::R8G8B8A8_UNorm,
-            SurfaceFormat::R8G8B8A8Srgb => Self::R8G8B8A8_UNorm_sRGB,
-            SurfaceFormat::B8G8R8A8Unorm => Self::B8G8R8A8_UNorm,
-            SurfaceFormat::B8G8R8A8Srgb => Self::B8G8R8A8_UNorm_sRGB,
-            SurfaceFormat::BC1Unorm => Self::BC1_UNorm,
-            SurfaceFormat::BC1Srgb => Self::BC1_UNorm_sRGB,
-            SurfaceFormat::BC2Unorm => Self::BC2_UNorm,
-            SurfaceFormat::BC2Srgb => Self::BC2_UNorm_sRGB,
-            SurfaceFormat::BC3Unorm => Self::BC3_UNorm,
-            SurfaceFormat::BC3Srgb => Self::BC3_UNorm_sRGB,
-            SurfaceFormat::BC4Unorm => Self::BC4_UNorm,
-            SurfaceFormat::BC4Snorm => Self::BC4_SNorm,
-            SurfaceFormat::BC5Unorm => Self::BC5_UNorm,
-            SurfaceFormat::BC5Snorm => Self::BC5_SNorm,
-            SurfaceFormat::BC6Sfloat => Self::BC6H_SF16,
-            SurfaceFormat::BC6Ufloat => Self::BC6H_UF16,
-            SurfaceFormat::BC7Unorm => Self::BC7_UNorm,
-            SurfaceFormat::BC7Srgb => Self::BC7_UNorm_sRGB,
+        f.try_into_dxgi().unwrap_or(DxgiFormat::Unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_bntx_x8r8g8b8_opaque_alpha() {
+        let mut dds = Dds::new_d3d(ddsfile::NewD3dParams {
+            height: 4,
+            width: 4,
+            depth: None,
+            format: D3DFormat::X8R8G8B8,
+            mipmap_levels: None,
+            caps2: None,
+        })
+        .unwrap();
+        dds.data = (0..4 * 4 * 4).map(|i| i as u8).collect();
+
+        let bntx = create_bntx("tex", &dds).unwrap();
+        let data = bntx.deswizzled_data().unwrap();
+        assert_eq!(4 * 4 * 4, data.len());
+        assert!(data.chunks_exact(4).all(|pixel| pixel[3] == 255));
+        assert!(data
+            .chunks_exact(4)
+            .zip(dds.data.chunks_exact(4))
+            .all(|(pixel, expected)| pixel[..3] == expected[..3]));
+    }
+
+    #[test]
+    fn preferred_alpha_mode() {
+        assert_eq!(
+            AlphaMode::Opaque,
+            SurfaceFormat::BC1Unorm.preferred_alpha_mode()
+        );
+        assert_eq!(
+            AlphaMode::Straight,
+            SurfaceFormat::R8G8B8A8Unorm.preferred_alpha_mode()
+        );
+        assert_eq!(
+            AlphaMode::Straight,
+            SurfaceFormat::BC7Unorm.preferred_alpha_mode()
+        );
+
+        for (format, data_len) in [
+            (SurfaceFormat::BC1Unorm, 8),
+            (SurfaceFormat::R8G8B8A8Unorm, 4 * 4 * 4),
+            (SurfaceFormat::BC7Unorm, 16),
+        ] {
+            let bntx =
+                BntxFile::from_image_data("tex", 4, 4, 1, 1, 1, format, &vec![0u8; data_len])
+                    .unwrap();
+            let dds = create_dds(&bntx).unwrap();
+            assert_eq!(
+                Some(format.preferred_alpha_mode()),
+                dds.header10.map(|h| h.alpha_mode)
+            );
         }
     }
 }
@@ -0,0 +1,118 @@
+//! Patricia trie dictionaries for looking up resources by name.
+use crate::{BntxStr, DictNode, DictSection};
+
+/// Builds the nodes of a [DictSection] from resource names.
+///
+/// Each node stores the index of the bit that distinguishes it from its parent.
+/// Bits are numbered starting from the least significant bit of the last character.
+#[derive(Debug)]
+pub(crate) struct DictBuilder {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+struct Node {
+    reference: i32,
+    left: usize,
+    right: usize,
+    name: String,
+}
+
+impl DictBuilder {
+    pub fn new() -> Self {
+        // The root node has an empty name and always uses its left child.
+        Self {
+            nodes: vec![Node {
+                reference: -1,
+                left: 0,
+                right: 0,
+                name: String::new(),
+            }],
+        }
+    }
+
+    /// Adds `name` to the dictionary. Duplicate names are ignored.
+    pub fn insert(&mut self, name: &str) {
+        let closest = &self.nodes[self.find_closest(name)].name;
+        let reference = match first_different_bit(name, closest) {
+            Some(bit) => bit as i32,
+            None => return,
+        };
+
+        // Find the edge to split while keeping the bit indices increasing along each path.
+        let mut parent = 0;
+        let mut child = self.nodes[0].left;
+        while self.nodes[parent].reference < self.nodes[child].reference
+            && self.nodes[child].reference < reference
+        {
+            parent = child;
+            child = self.child(child, name);
+        }
+
+        let index = self.nodes.len();
+        let (left, right) = if bit(name, reference) {
+            (child, index)
+        } else {
+            (index, child)
+        };
+        self.nodes.push(Node {
+            reference,
+            left,
+            right,
+            name: name.to_owned(),
+        });
+
+        if parent != 0 && bit(name, self.nodes[parent].reference) {
+            self.nodes[parent].right = index;
+        } else {
+            self.nodes[parent].left = index;
+        }
+    }
+
+    pub fn build(self) -> DictSection {
+        DictSection {
+            node_count: self.nodes.len() as u32 - 1,
+            nodes: self
+                .nodes
+                .into_iter()
+                .map(|n| DictNode {
+                    reference: n.reference,
+                    left_index: n.left as u16,
+                    right_index: n.right as u16,
+                    name: BntxStr::from(n.name),
+                })
+                .collect(),
+        }
+    }
+
+    /// The index of the node with the name sharing the most bits with `name`.
+    fn find_closest(&self, name: &str) -> usize {
+        let mut parent = 0;
+        let mut child = self.nodes[0].left;
+        while self.nodes[parent].reference < self.nodes[child].reference {
+            parent = child;
+            child = self.child(child, name);
+        }
+        child
+    }
+
+    fn child(&self, index: usize, name: &str) -> usize {
+        let node = &self.nodes[index];
+        if index != 0 && bit(name, node.reference) {
+            node.right
+        } else {
+            node.left
+        }
+    }
+}
+
+fn bit(name: &str, bit: i32) -> bool {
+    let bytes = name.as_bytes();
+    let byte_index = bit as usize / 8;
+    byte_index < bytes.len() && (bytes[bytes.len() - 1 - byte_index] >> (bit % 8)) & 1 != 0
+}
+
+fn first_different_bit(a: &str, b: &str) -> Option<usize> {
+    let bit_count = a.len().max(b.len()) * 8;
+    (0..bit_count).find(|i| bit(a, *i as i32) != bit(b, *i as i32))
+}
@@ -0,0 +1,218 @@
+//! Tables of [SurfaceFormat] grouped by their capabilities.
+//!
+//! # Examples
+/**
+```rust
+use bntx::formats::{COMPRESSED_FORMATS, SRGB_FORMATS};
+use bntx::SurfaceFormat;
+
+assert!(COMPRESSED_FORMATS.contains(&SurfaceFormat::BC7Srgb));
+assert!(SRGB_FORMATS.contains(&SurfaceFormat::BC7Srgb));
+```
+ */
+use crate::SurfaceFormat;
+
+/// Block compressed formats with blocks larger than a single pixel.
+pub const COMPRESSED_FORMATS: &[SurfaceFormat] = &[
+    SurfaceFormat::BC1Unorm,
+    SurfaceFormat::BC1Srgb,
+    SurfaceFormat::BC2Unorm,
+    SurfaceFormat::BC2Srgb,
+    SurfaceFormat::BC3Unorm,
+    SurfaceFormat::BC3Srgb,
+    SurfaceFormat::BC4Unorm,
+    SurfaceFormat::BC4Snorm,
+    SurfaceFormat::BC5Unorm,
+    SurfaceFormat::BC5Snorm,
+    SurfaceFormat::BC6Sfloat,
+    SurfaceFormat::BC6Ufloat,
+    SurfaceFormat::BC7Unorm,
+    SurfaceFormat::BC7Srgb,
+    SurfaceFormat::Etc2Rgb8Unorm,
+    SurfaceFormat::EacR11Unorm,
+    SurfaceFormat::Astc4x4Unorm,
+    SurfaceFormat::Astc4x4Srgb,
+    SurfaceFormat::Astc6x6Unorm,
+    SurfaceFormat::Astc6x6Srgb,
+    SurfaceFormat::Astc8x8Unorm,
+    SurfaceFormat::Astc8x8Srgb,
+];
+
+/// Uncompressed formats storing each pixel separately.
+pub const UNCOMPRESSED_FORMATS: &[SurfaceFormat] = &[
+    SurfaceFormat::R8Unorm,
+    SurfaceFormat::R32Sfloat,
+    SurfaceFormat::R32Uint,
+    SurfaceFormat::R32Sint,
+    SurfaceFormat::R16Unorm,
+    SurfaceFormat::R16Snorm,
+    SurfaceFormat::R16Sfloat,
+    SurfaceFormat::R8G8Unorm,
+    SurfaceFormat::R8G8Snorm,
+    SurfaceFormat::R16G16Unorm,
+    SurfaceFormat::R16G16Snorm,
+    SurfaceFormat::R16G16Sfloat,
+    SurfaceFormat::R8G8B8A8Unorm,
+    SurfaceFormat::R8G8B8A8Srgb,
+    SurfaceFormat::B8G8R8A8Unorm,
+    SurfaceFormat::B8G8R8A8Srgb,
+    SurfaceFormat::R16G16B16A16Unorm,
+    SurfaceFormat::R16G16B16A16Snorm,
+    SurfaceFormat::R16G16B16A16Sfloat,
+    SurfaceFormat::R10G10B10A2Unorm,
+];
+
+/// Formats that store color values with sRGB gamma.
+pub const SRGB_FORMATS: &[SurfaceFormat] = &[
+    SurfaceFormat::R8G8B8A8Srgb,
+    SurfaceFormat::B8G8R8A8Srgb,
+    SurfaceFormat::BC1Srgb,
+    SurfaceFormat::BC2Srgb,
+    SurfaceFormat::BC3Srgb,
+    SurfaceFormat::BC7Srgb,
+    SurfaceFormat::Astc4x4Srgb,
+    SurfaceFormat::Astc6x6Srgb,
+    SurfaceFormat::Astc8x8Srgb,
+];
+
+/// Formats that store floating point values.
+pub const FLOAT_FORMATS: &[SurfaceFormat] = &[
+    SurfaceFormat::R32Sfloat,
+    SurfaceFormat::R16Sfloat,
+    SurfaceFormat::R16G16Sfloat,
+    SurfaceFormat::R16G16B16A16Sfloat,
+    SurfaceFormat::BC6Sfloat,
+    SurfaceFormat::BC6Ufloat,
+];
+
+/// How much information is preserved when converting image data from one [SurfaceFormat] to another.
+///
+/// This only classifies conversions and does not describe any conversion provided by this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionSupport {
+    /// Every value of the source format can be represented exactly by the target format.
+    /// This includes changing between the sRGB and linear variants of a format.
+    Lossless,
+    /// The conversion loses channels, precision, range, or signed or floating point values.
+    Lossy,
+    /// The compressed data must be decoded before converting.
+    RequiresDecoder,
+    /// The conversion requires an encoder for the compressed target format.
+    Unsupported,
+}
+
+impl ConversionSupport {
+    /// The support for converting image data from `from` to `to`.
+    pub fn new(from: SurfaceFormat, to: SurfaceFormat) -> Self {
+        // The upper byte of the format identifies the channel layout.
+        let same_layout = (from as u32) >> 8 == (to as u32) >> 8
+            && ChannelType::new(from) == ChannelType::new(to);
+
+        if from == to || same_layout {
+            Self::Lossless
+        } else if COMPRESSED_FORMATS.contains(&from) {
+            Self::RequiresDecoder
+        } else if COMPRESSED_FORMATS.contains(&to) {
+            Self::Unsupported
+        } else {
+            let (from_min, from_max) = channel_bits(from);
+            let (to_min, to_max) = channel_bits(to);
+
+            // Check every combination of channel sizes for formats like R10G10B10A2.
+            let channels_lossless = [from_min, from_max].iter().all(|from_bits| {
+                [to_min, to_max].iter().all(|to_bits| {
+                    is_channel_lossless(
+                        ChannelType::new(from),
+                        *from_bits,
+                        ChannelType::new(to),
+                        *to_bits,
+                    )
+                })
+            });
+
+            if to.channel_count() >= from.channel_count() && channels_lossless {
+                Self::Lossless
+            } else {
+                Self::Lossy
+            }
+        }
+    }
+}
+
+/// How the bits of each channel are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChannelType {
+    Unorm,
+    Snorm,
+    Uint,
+    Sint,
+    Float,
+}
+
+impl ChannelType {
+    fn new(format: SurfaceFormat) -> Self {
+        match format {
+            SurfaceFormat::R32Uint => Self::Uint,
+            SurfaceFormat::R32Sint => Self::Sint,
+            _ if format.is_floating_point() => Self::Float,
+            _ if format.is_signed() => Self::Snorm,
+            _ => Self::Unorm,
+        }
+    }
+}
+
+/// Returns `true` if every value of a channel can be represented exactly after converting.
+fn is_channel_lossless(
+    from: ChannelType,
+    from_bits: usize,
+    to: ChannelType,
+    to_bits: usize,
+) -> bool {
+    // Normalized values are integer steps between 0.0 and 1.0.
+    // The target steps must be a multiple of the source steps to represent each value.
+    let unorm_steps = |bits: usize| (1u64 << bits) - 1;
+    let snorm_steps = |bits: usize| (1u64 << (bits - 1)) - 1;
+
+    // Floats represent integers exactly up to the size of the significand.
+    let significand_bits = |bits: usize| match bits {
+        16 => 11,
+        32 => 24,
+        _ => 0,
+    };
+
+    match (from, to) {
+        (ChannelType::Unorm, ChannelType::Unorm) => {
+            unorm_steps(to_bits) % unorm_steps(from_bits) == 0
+        }
+        (ChannelType::Unorm, ChannelType::Snorm) => {
+            snorm_steps(to_bits) % unorm_steps(from_bits) == 0
+        }
+        (ChannelType::Snorm, ChannelType::Snorm) => {
+            snorm_steps(to_bits) % snorm_steps(from_bits) == 0
+        }
+        (ChannelType::Uint, ChannelType::Uint) | (ChannelType::Sint, ChannelType::Sint) => {
+            to_bits >= from_bits
+        }
+        // The sign bit leaves one less bit for positive values.
+        (ChannelType::Uint, ChannelType::Sint) => to_bits > from_bits,
+        (ChannelType::Unorm, ChannelType::Float) | (ChannelType::Uint, ChannelType::Float) => {
+            significand_bits(to_bits) >= from_bits
+        }
+        (ChannelType::Snorm, ChannelType::Float) | (ChannelType::Sint, ChannelType::Float) => {
+            significand_bits(to_bits) >= from_bits - 1
+        }
+        (ChannelType::Float, ChannelType::Float) => to_bits >= from_bits,
+        _ => false,
+    }
+}
+
+/// The smallest and largest number of bits for the channels of an uncompressed format.
+fn channel_bits(format: SurfaceFormat) -> (usize, usize) {
+    match format {
+        SurfaceFormat::R10G10B10A2Unorm => (2, 10),
+        _ => {
+            let bits = format.bytes_per_pixel() * 8 / format.channel_count() as usize;
+            (bits, bits)
+        }
+    }
+}
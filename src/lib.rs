@@ -2,46 +2,256 @@ use binrw::binread;
 use binrw::binrw;
 use binrw::prelude::*;
 use binrw::BinWrite;
-use binrw::VecArgs;
 use binrw::{FilePtr16, FilePtr32, FilePtr64, NullString};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::io::SeekFrom;
+use std::num::NonZeroUsize;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 use std::{fmt, io};
-use tegra_swizzle::block_height_mip0;
 use tegra_swizzle::div_round_up;
 use tegra_swizzle::mip_block_height;
-use tegra_swizzle::surface::{deswizzle_surface, swizzle_surface, BlockDim};
+use tegra_swizzle::surface::{
+    deswizzle_surface, deswizzled_surface_size, swizzle_surface, swizzled_surface_size, BlockDim,
+};
+use tegra_swizzle::swizzle::{deswizzle_block_linear, deswizzled_mip_size, swizzled_mip_size};
 use tegra_swizzle::BlockHeight;
+use thiserror::Error;
 
 // TODO: Add module level docs for basic usage.
 // TODO: Make this optional.
 pub mod dds;
+pub mod formats;
+pub mod util;
+
+#[cfg(feature = "ndarray")]
+mod array;
+mod builder;
+mod dict;
+pub use builder::{BntxFileBuilder, ColorSpace, DataLayout};
+
+/// Re-exports of the most commonly used types.
+/**
+```rust
+use bntx::prelude::*;
+```
+ */
+pub mod prelude {
+    pub use crate::{
+        BntxError, BntxFile, BntxFileBuilder, SurfaceFormat, TextureDimension, TextureViewDimension,
+    };
+}
+
+use util::align;
 
 const BNTX_HEADER_SIZE: usize = 0x20;
-const NX_HEADER_SIZE: usize = 0x28;
+// The NX magic, texture count, BRTI, BRTD, and dictionary pointers, and memory pool pointer.
+const NX_HEADER_SIZE: usize = 4 + size_of::<u32>() + size_of::<u64>() * 4;
+const _: () = assert!(NX_HEADER_SIZE == 0x28);
 const HEADER_SIZE: usize = BNTX_HEADER_SIZE + NX_HEADER_SIZE;
+// The memory pool is reserved for the GPU memory allocator's bookkeeping at runtime.
+// The region is never parsed and is always written as zeros.
 const MEM_POOL_SIZE: usize = 0x150;
+const MEM_POOL_ADDRESS: u64 = 0x58;
 const DATA_PTR_SIZE: usize = 8;
 
 const START_OF_STR_SECTION: usize = HEADER_SIZE + MEM_POOL_SIZE + DATA_PTR_SIZE;
+const _: () = assert!(START_OF_STR_SECTION == 0x1A0);
 
-const STR_HEADER_SIZE: usize = 0x14;
+// The _STR magic, block size, block offset, and string count.
+const STR_HEADER_SIZE: usize = 4 + size_of::<u32>() + size_of::<u64>() + size_of::<u32>();
+const _: () = assert!(STR_HEADER_SIZE == 0x14);
 const EMPTY_STR_SIZE: usize = 4;
 
-const FILENAME_STR_OFFSET: usize = START_OF_STR_SECTION + STR_HEADER_SIZE + EMPTY_STR_SIZE;
-
+// The BRTD is usually padded to this position so the image data starts at 0x1000.
+// The BRTD position for a file is determined by the first mipmap offset.
+// The NX header BRTD pointer is filled in from the actual position when writing.
+// Larger string or dictionary sections return an error instead of moving the BRTD.
 const BRTD_SECTION_START: usize = 0xFF0;
-const SIZE_OF_BRTD: usize = 0x10;
-const START_OF_TEXTURE_DATA: usize = BRTD_SECTION_START + SIZE_OF_BRTD;
+// The BRTD magic, padding, and section size before the image data.
+// The BRTD section size includes this header, but the BRTI image size does not.
+const BRTD_HEADER_SIZE: usize = 0x10;
+
+// The Tegra X1 tiles surfaces into blocks of GOBs that are each 512 bytes.
+const GOB_SIZE_IN_BYTES: u32 = 512;
+
+#[derive(Debug, Error)]
+pub enum BntxError {
+    #[error("failed to swizzle surface")]
+    Swizzle(#[from] tegra_swizzle::SwizzleError),
+
+    #[error("failed to swizzle {width}x{height}x{depth} {format:?} surface with {mipmap_count} mipmaps and {layer_count} layers")]
+    SwizzleSurface {
+        width: u32,
+        height: u32,
+        depth: u32,
+        format: SurfaceFormat,
+        mipmap_count: u32,
+        layer_count: u32,
+        source: tegra_swizzle::SwizzleError,
+    },
+
+    #[error("{format:?} does not support textures with a depth of {depth}")]
+    IncompatibleDimensionForFormat { format: SurfaceFormat, depth: u32 },
+
+    #[error("3D textures with a depth of {depth} must have a single layer instead of {layer_count} layers")]
+    AmbiguousLayerDepth { layer_count: u32, depth: u32 },
+
+    #[error("requested {requested} mipmaps but the dimensions only support {max} mipmaps")]
+    TooManyMipmaps { requested: u32, max: u32 },
+
+    #[error("sections before the BRTD require {size} bytes but the BRTD starts at 0x{max:X}")]
+    HeaderTooLarge { size: usize, max: usize },
+
+    #[error(
+        "data length {len} is not a multiple of the {block_size} byte block size for {format:?}"
+    )]
+    UnalignedDataLength {
+        format: SurfaceFormat,
+        len: usize,
+        block_size: usize,
+    },
+
+    #[error("expected at least {expected} bytes of image data but found {actual} bytes")]
+    NotEnoughData { expected: usize, actual: usize },
+
+    #[error("{operation} is not supported for {format:?}")]
+    UnsupportedOperation {
+        operation: &'static str,
+        format: SurfaceFormat,
+    },
+
+    #[error("the region at ({x}, {y}) with size {width}x{height} is out of bounds")]
+    InvalidRegion {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+
+    #[error("expected {expected} bytes for mipmap {mip} but found {actual} bytes")]
+    InvalidMipmapSize {
+        mip: u32,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("mipmap offset 0x{offset:X} for mipmap {mip} is not after the previous offset 0x{previous:X}")]
+    UnorderedMipmapOffsets {
+        mip: u32,
+        offset: u64,
+        previous: u64,
+    },
+
+    #[error("the string section starts at 0x{actual:X} instead of 0x{expected:X}")]
+    UnexpectedStrSectionStart { expected: u64, actual: u64 },
+
+    #[error("expected format {expected:?} but found {actual:?}")]
+    IncompatibleFormat {
+        expected: SurfaceFormat,
+        actual: Option<SurfaceFormat>,
+    },
+
+    #[error("expected {field} {expected} but found {field} {actual}")]
+    DimensionMismatch {
+        field: &'static str,
+        expected: u32,
+        actual: u32,
+    },
+
+    #[error("expected {expected} bytes of image data but found {actual} bytes")]
+    DataSizeMismatch { expected: usize, actual: usize },
+
+    #[error("{zero_blocks} of {total_blocks} compressed blocks are all zeros")]
+    SuspectPixelData {
+        zero_blocks: usize,
+        total_blocks: usize,
+    },
+
+    #[error("the {location} name {actual:?} does not match the file name {expected:?}")]
+    InconsistentName {
+        location: &'static str,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("the offset or size {offset} is past the maximum u32 offset")]
+    OffsetOverflow { offset: usize },
+
+    #[error("the texture name {0:?} contains non-ASCII characters")]
+    NonAsciiName(String),
+
+    #[error("images with {0} channels are not supported")]
+    UnsupportedChannelCount(usize),
+
+    #[error("{0} is not a valid mipmap count")]
+    InvalidMipmapCount(u32),
+
+    #[error("layer {layer} mipmap {mip} is out of range")]
+    InvalidSubresource { layer: u32, mip: u32 },
+
+    #[error("failed to decode image")]
+    Image(#[from] image::ImageError),
+
+    #[error("failed to create DDS")]
+    Dds(#[from] ddsfile::Error),
+
+    #[error("surface format 0x{0:X} is not supported")]
+    UnsupportedFormat(u32),
+
+    #[error("expected magic {expected:?} but found {got:?}")]
+    InvalidMagic {
+        expected: &'static [u8],
+        got: Vec<u8>,
+    },
+
+    #[error("failed to read or write data")]
+    Io(#[source] binrw::Error),
+}
+
+impl From<binrw::Error> for BntxError {
+    fn from(e: binrw::Error) -> Self {
+        // Unknown formats are reported while parsing the BRTI.
+        if let Some(BntxError::UnsupportedFormat(value)) = e.root_cause().custom_err::<BntxError>()
+        {
+            return Self::UnsupportedFormat(*value);
+        }
+        Self::Io(e)
+    }
+}
+
+impl From<io::Error> for BntxError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e.into())
+    }
+}
 
 // TODO: Decompile syroot.nintentools.bntx from switch toolbox to figure out how writing works.
-#[derive(BinRead, Debug)]
+#[derive(BinRead)]
+#[br(import(endian: Option<binrw::Endian>))]
 pub struct BntxFile {
+    #[br(args(endian))]
     header: BntxHeader,
 
-    #[br(is_little = header.bom == ByteOrder::LittleEndian)]
+    #[br(is_little = is_little_endian(endian, header.bom))]
     nx_header: NxHeader,
+
+    // Deswizzling is expensive, so cache the result of the first call to deswizzled_data.
+    // This must be reset whenever the image data or surface info changes.
+    #[br(default)]
+    deswizzled_data: OnceLock<Vec<u8>>,
+}
+
+impl fmt::Debug for BntxFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BntxFile")
+            .field("header", &self.header)
+            .field("nx_header", &self.nx_header)
+            .finish()
+    }
 }
 
 impl BntxFile {
@@ -69,843 +279,4543 @@ impl BntxFile {
         self.nx_header.brti.format
     }
 
+    /// Sets the component selector used to map the stored channels when sampling.
+    pub fn apply_channel_format_hint(&mut self, channel: ChannelHint) {
+        self.nx_header.brti.comp_sel = channel.comp_sel();
+    }
+
+    /// The width and height of the base mipmap rounded up to the format's block dimensions.
+    /// This is the allocated size for block compressed formats like BC7.
+    pub fn compute_footprint(&self) -> (u32, u32) {
+        let info = &self.nx_header.brti;
+        let block_dim = info.format.block_dim();
+        let width =
+            div_round_up(info.width as usize, block_dim.width.get()) * block_dim.width.get();
+        let height =
+            div_round_up(info.height as usize, block_dim.height.get()) * block_dim.height.get();
+        (width as u32, height as u32)
+    }
+
+    /// The dimensions and swizzled data location for each mipmap.
+    pub fn mipmap_infos(&self) -> Vec<MipmapInfo> {
+        let info = &self.nx_header.brti;
+        let block_dim = info.format.block_dim();
+
+        info.mipmaps
+            .mipmap_offsets
+            .iter()
+            .enumerate()
+            .map(|(mip, offset)| {
+                let width = (info.width >> mip).max(1);
+                let height = (info.height >> mip).max(1);
+                let depth = (info.depth >> mip).max(1);
+
+                let height_in_blocks = div_round_up(height as usize, block_dim.height.get());
+                let size = swizzled_mip_size(
+                    div_round_up(width as usize, block_dim.width.get()),
+                    height_in_blocks,
+                    div_round_up(depth as usize, block_dim.depth.get()),
+                    mip_block_height(height_in_blocks, self.block_height_mip0()),
+                    info.format.bytes_per_pixel(),
+                );
+
+                MipmapInfo {
+                    level: mip as u32,
+                    width,
+                    height,
+                    depth,
+                    offset: *offset,
+                    size,
+                }
+            })
+            .collect()
+    }
+
+    /// The size in bytes of the unswizzled data for all layers and mipmaps.
+    /// This is the expected size of the data for [BntxFile::from_image_data].
+    pub fn compute_surface_size_unswizzled(&self) -> usize {
+        let info = &self.nx_header.brti;
+        unswizzled_surface_size(
+            info.width,
+            info.height,
+            info.depth,
+            info.format,
+            info.mipmap_count as u32,
+            info.layer_count,
+        )
+    }
+
+    /// Iterates over the swizzled data for each mipmap of the first array layer
+    /// as `(level, width, height, data)`.
+    pub fn iter_mipmaps(&self) -> impl Iterator<Item = (u32, u32, u32, &[u8])> {
+        self.mipmap_infos()
+            .into_iter()
+            .zip(self.mipmap_byte_ranges())
+            .map(move |(info, range)| {
+                (
+                    info.level,
+                    info.width,
+                    info.height,
+                    &self.nx_header.brtd.image_data[range],
+                )
+            })
+    }
+
+    /// The byte range in the swizzled image data for each mipmap of the first array layer.
+    fn mipmap_byte_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        let data_len = self.nx_header.brtd.image_data.len();
+        let infos = self.mipmap_infos();
+
+        // The mipmap offsets are absolute, but the first mipmap starts the image data.
+        let base_offset = infos.first().map(|i| i.offset).unwrap_or_default();
+        infos
+            .iter()
+            .map(|info| {
+                let start = (info.offset.saturating_sub(base_offset) as usize).min(data_len);
+                let end = (start + info.size).min(data_len);
+                start..end
+            })
+            .collect()
+    }
+
     /// The deswizzled image data for all layers and mipmaps.
-    pub fn deswizzled_data(&self) -> Result<Vec<u8>, tegra_swizzle::SwizzleError> {
+    /// The result is cached, so subsequent calls only need to copy the data.
+    /// Use [BntxFile::deswizzled_data_ref] to avoid the copy.
+    pub fn deswizzled_data(&self) -> Result<Vec<u8>, BntxError> {
+        self.deswizzled_data_ref().map(<[u8]>::to_vec)
+    }
+
+    /// The cached deswizzled image data like [BntxFile::deswizzled_data] without copying.
+    pub fn deswizzled_data_ref(&self) -> Result<&[u8], BntxError> {
+        if let Some(data) = self.deswizzled_data.get() {
+            return Ok(data);
+        }
+
         let info = &self.nx_header.brti;
 
-        deswizzle_surface(
+        // Trailing zeros may be omitted from the stored data.
+        let mut swizzled = Cow::Borrowed(&self.nx_header.brtd.image_data[..]);
+        if swizzled.len() < info.image_size as usize {
+            swizzled.to_mut().resize(info.image_size as usize, 0);
+        }
+
+        let data = deswizzle_surface(
             info.width as usize,
             info.height as usize,
             info.depth as usize,
-            &self.nx_header.brtd.image_data,
+            &swizzled,
             info.format.block_dim(),
-            Some(BlockHeight::new(2u32.pow(info.block_height_log2) as usize).unwrap()),
+            Some(self.block_height_mip0()),
             info.format.bytes_per_pixel(),
             info.mipmap_count as usize,
             info.layer_count as usize,
-        )
+        )?;
+
+        Ok(self.deswizzled_data.get_or_init(|| data))
+    }
+
+    /// Iterates over the deswizzled image data for each array layer.
+    /// Each layer contains all mipmaps and is only deswizzled when requested.
+    pub fn iter_array_layers(&self) -> impl Iterator<Item = Result<Vec<u8>, BntxError>> + '_ {
+        let info = &self.nx_header.brti;
+        let stride = self.layer_stride();
+
+        (0..info.layer_count as usize).map(move |layer| {
+            let data = &self.nx_header.brtd.image_data;
+            let start = (layer * stride).min(data.len());
+            let end = ((layer + 1) * stride).min(data.len());
+
+            // Trailing zeros may be omitted from the stored data.
+            let mut swizzled = Cow::Borrowed(&data[start..end]);
+            if swizzled.len() < stride {
+                swizzled.to_mut().resize(stride, 0);
+            }
+
+            deswizzle_surface(
+                info.width as usize,
+                info.height as usize,
+                info.depth as usize,
+                &swizzled,
+                info.format.block_dim(),
+                Some(self.block_height_mip0()),
+                info.format.bytes_per_pixel(),
+                info.mipmap_count as usize,
+                1,
+            )
+            .map_err(Into::into)
+        })
     }
 
-    pub fn write<W: io::Write + io::Seek>(
+    /// The deswizzled image data like [BntxFile::deswizzled_data]
+    /// and the layout describing where each layer and mipmap starts.
+    pub fn deswizzle_to_vec_with_layout(&self) -> Result<(Vec<u8>, TextureLayout), BntxError> {
+        let data = self.deswizzled_data()?;
+
+        let info = &self.nx_header.brti;
+        let block_dim = info.format.block_dim();
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        for _ in 0..info.layer_count {
+            for mip in 0..info.mipmap_count as usize {
+                offsets.push(offset);
+                offset += deswizzled_mip_size(
+                    div_round_up((info.width as usize >> mip).max(1), block_dim.width.get()),
+                    div_round_up((info.height as usize >> mip).max(1), block_dim.height.get()),
+                    div_round_up((info.depth as usize >> mip).max(1), block_dim.depth.get()),
+                    info.format.bytes_per_pixel(),
+                );
+            }
+        }
+
+        Ok((
+            data,
+            TextureLayout {
+                mip_order: MipOrder::LargestFirst,
+                layer_order: LayerOrder::LayerMajor,
+                offsets,
+            },
+        ))
+    }
+
+    /// Reads and deswizzles the data for a single array layer and mipmap from `reader`
+    /// without loading the rest of the surface.
+    /// The `reader` should contain the same file used to create `self`.
+    ///
+    /// Bytes past the end of the stored image data are treated as zeros
+    /// like in [BntxFile::deswizzled_data].
+    pub fn read_mipmap_from_reader<R: Read + Seek>(
         &self,
-        writer: &mut W,
-    ) -> Result<(), binrw::error::Error> {
-        let endian = binrw::Endian::Little;
-        self.header.write_options(writer, endian, self)?;
-        self.nx_header.write_options(writer, endian, self)?;
+        reader: &mut R,
+        layer: u32,
+        mip: u32,
+    ) -> Result<Vec<u8>, BntxError> {
+        let info = &self.nx_header.brti;
+        if layer >= info.layer_count || mip >= info.mipmap_count as u32 {
+            return Err(BntxError::InvalidSubresource { layer, mip });
+        }
 
-        (
-            // memory pool
-            &[0u8; 0x150][..],
-            (START_OF_STR_SECTION
-                + self.header.inner.str_section.get_size()
-                + self.nx_header.dict.get_size()) as u64,
-            &self.header.inner.str_section,
-            &self.nx_header.dict,
-        )
-            .write_options(writer, endian, ())?;
+        let block_dim = info.format.block_dim();
+        let bytes_per_pixel = info.format.bytes_per_pixel();
 
-        self.nx_header.brti.write_options(writer, endian, self)?;
+        let mip_width = div_round_up((info.width as usize >> mip).max(1), block_dim.width.get());
+        let mip_height = div_round_up((info.height as usize >> mip).max(1), block_dim.height.get());
+        let mip_depth = div_round_up((info.depth as usize >> mip).max(1), block_dim.depth.get());
+        let mip_block_height = mip_block_height(mip_height, self.block_height_mip0());
+
+        let size = swizzled_mip_size(
+            mip_width,
+            mip_height,
+            mip_depth,
+            mip_block_height,
+            bytes_per_pixel,
+        );
+        let offset = info.mipmaps.mipmap_offsets[mip as usize]
+            + (layer as usize * self.layer_stride()) as u64;
 
-        vec![0u8; 512].write_options(writer, endian, ())?;
+        // Trailing zeros may be omitted from the stored data.
+        let data_end = info.mipmaps.mipmap_offsets[0] + self.nx_header.brtd.image_data.len() as u64;
+        let stored_size = data_end.saturating_sub(offset).min(size as u64) as usize;
 
-        for offset in &self.nx_header.brti.mipmaps.mipmap_offsets {
-            offset.write_options(writer, endian, ())?;
+        let mut swizzled = vec![0u8; size];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut swizzled[..stored_size])?;
+
+        deswizzle_block_linear(
+            mip_width,
+            mip_height,
+            mip_depth,
+            &swizzled,
+            mip_block_height,
+            bytes_per_pixel,
+        )
+        .map_err(Into::into)
+    }
+
+    /// Creates a new [BntxFile] containing the region of the base mipmap
+    /// with top left corner `x`, `y` and size `width` by `height` for all layers.
+    ///
+    /// Only uncompressed formats are supported.
+    /// Returns [BntxError::UnsupportedOperation] for block compressed formats.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<BntxFile, BntxError> {
+        let info = &self.nx_header.brti;
+        if info.format.block_dim() != BlockDim::uncompressed() {
+            return Err(BntxError::UnsupportedOperation {
+                operation: "cropping",
+                format: info.format,
+            });
         }
-        let mipmaps_offset = writer.stream_position()?;
 
-        let padding_size = BRTD_SECTION_START as u64 - mipmaps_offset;
-        vec![0u8; padding_size as usize].write_options(writer, endian, ())?;
+        let out_of_bounds = x.checked_add(width).is_none_or(|right| right > info.width)
+            || y.checked_add(height)
+                .is_none_or(|bottom| bottom > info.height);
+        if width == 0 || height == 0 || out_of_bounds {
+            return Err(BntxError::InvalidRegion {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
 
-        self.nx_header.brtd.write_options(writer, endian, ())?;
+        let data = self.deswizzled_data_ref()?;
 
-        self.header
-            .inner
-            .reloc_table
-            .write_options(writer, endian, ())?;
+        let bytes_per_pixel = info.format.bytes_per_pixel();
+        let layer_size = data.len() / (info.layer_count as usize).max(1);
+        let src_stride = info.width as usize * bytes_per_pixel;
+        let row_size = width as usize * bytes_per_pixel;
 
-        Ok(())
+        let mut cropped = Vec::new();
+        for layer in 0..info.layer_count as usize {
+            for z in 0..info.depth as usize {
+                let slice_start = layer * layer_size + z * src_stride * info.height as usize;
+                for row in y as usize..(y + height) as usize {
+                    let start = slice_start + row * src_stride + x as usize * bytes_per_pixel;
+                    cropped.extend_from_slice(&data[start..start + row_size]);
+                }
+            }
+        }
+
+        let mut bntx = BntxFileBuilder::new(
+            self.header.inner.file_name.as_str(),
+            width,
+            height,
+            info.format,
+            &cropped,
+        )
+        .depth(info.depth)
+        .layer_count(info.layer_count)
+        .swizzle(info.swizzle)
+        .unk2(info.unk2)
+        .multi_sample_count(info.multi_sample_count)
+        .flags(info.flags)
+        .parent_offset(info.parent_addr)
+        .format_version(self.header.version.0, self.header.version.1)
+        .brtd_offset(self.brtd_start())
+        .build()?;
+        bntx.nx_header.brti.comp_sel = info.comp_sel;
+        Ok(bntx)
     }
 
-    pub fn from_image(
-        img: image::DynamicImage,
-        name: &str,
-    ) -> Result<Self, tegra_swizzle::SwizzleError> {
-        let data = img.to_rgba8().into_raw();
+    /// Reinterprets the existing image data using new dimensions and updates
+    /// the block height, mipmap offsets, and swizzled data to match.
+    ///
+    /// The unswizzled size for the new dimensions must match the current unswizzled size.
+    /// Returns [BntxError::DataSizeMismatch] otherwise.
+    /// The file is left unchanged if an error occurs.
+    pub fn set_dimensions(&mut self, width: u32, height: u32, depth: u32) -> Result<(), BntxError> {
+        let info = &self.nx_header.brti;
+        let expected = unswizzled_surface_size(
+            width,
+            height,
+            depth,
+            info.format,
+            info.mipmap_count as u32,
+            info.layer_count,
+        );
+        let data = self.deswizzled_data_ref()?;
+        if expected != data.len() {
+            return Err(BntxError::DataSizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
 
-        Self::from_image_data(
-            name,
-            img.width(),
-            img.height(),
-            1,
-            1,
-            1,
-            SurfaceFormat::R8G8B8A8Srgb,
-            &data,
+        let bntx = BntxFileBuilder::new(
+            self.header.inner.file_name.as_str(),
+            width,
+            height,
+            info.format,
+            data,
         )
+        .depth(depth)
+        .mipmap_count(info.mipmap_count as u32)
+        .layer_count(info.layer_count)
+        .swizzle(info.swizzle)
+        .unk2(info.unk2)
+        .multi_sample_count(info.multi_sample_count)
+        .flags(info.flags)
+        .parent_offset(info.parent_addr)
+        .brtd_offset(self.brtd_start())
+        .build()?;
+
+        let mut info = info.clone();
+        info.width = width;
+        info.height = height;
+        info.depth = depth;
+        info.block_height_log2 = bntx.nx_header.brti.block_height_log2;
+        info.align = bntx.nx_header.brti.align;
+        info.texture_dimension = bntx.nx_header.brti.texture_dimension;
+        info.texture_view_dimension = bntx.nx_header.brti.texture_view_dimension;
+        info.mipmaps = bntx.nx_header.brti.mipmaps;
+        self.replace_surface(info, bntx.nx_header.brtd.image_data)
     }
 
-    /// Create a [BntxFile] from unswizzled image data.
-    pub fn from_image_data(
-        name: &str,
-        width: u32,
-        height: u32,
-        depth: u32,
-        mipmap_count: u32,
-        layer_count: u32,
-        format: SurfaceFormat,
-        data: &[u8],
-    ) -> Result<Self, tegra_swizzle::SwizzleError> {
-        // Let tegra_swizzle calculate the block height.
-        // This matches the value inferred for missing block heights like in nutexb.
-        let block_dim = format.block_dim();
-        let block_height = block_height_mip0(div_round_up(height as usize, block_dim.height.get()));
-
-        let block_height_log2 = match block_height {
-            BlockHeight::One => 0,
-            BlockHeight::Two => 1,
-            BlockHeight::Four => 2,
-            BlockHeight::Eight => 3,
-            BlockHeight::Sixteen => 4,
-            BlockHeight::ThirtyTwo => 5,
-        };
+    /// Changes the number of mipmaps by removing mipmaps or adding zeroed mipmaps.
+    /// The data for existing mipmaps is preserved.
+    ///
+    /// Returns [BntxError::HeaderTooLarge] if the additional mipmap offsets would overlap the BRTD.
+    /// The file is left unchanged if an error occurs.
+    pub fn set_mipmap_count(&mut self, count: u32) -> Result<(), BntxError> {
+        let info = &self.nx_header.brti;
+        if count == 0 {
+            return Err(BntxError::InvalidMipmapCount(count));
+        }
+        let max = max_mipmap_count(info.width, info.height, info.depth);
+        if count > max {
+            return Err(BntxError::TooManyMipmaps {
+                requested: count,
+                max,
+            });
+        }
 
-        let bytes_per_pixel = format.bytes_per_pixel();
+        // Each mipmap offset is stored before the BRTD.
+        let size = size_before_brtd(
+            self.header.inner.str_section.get_size(),
+            self.nx_header.dict.get_size(),
+            count as usize,
+        );
+        if size > self.brtd_start() {
+            return Err(BntxError::HeaderTooLarge {
+                size,
+                max: self.brtd_start(),
+            });
+        }
+
+        let block_dim = info.format.block_dim();
+        let bytes_per_pixel = info.format.bytes_per_pixel();
+        let mip_sizes: Vec<_> = (0..count.max(info.mipmap_count as u32) as usize)
+            .map(|mip| {
+                deswizzled_mip_size(
+                    div_round_up((info.width as usize >> mip).max(1), block_dim.width.get()),
+                    div_round_up((info.height as usize >> mip).max(1), block_dim.height.get()),
+                    div_round_up((info.depth as usize >> mip).max(1), block_dim.depth.get()),
+                    bytes_per_pixel,
+                )
+            })
+            .collect();
 
+        let old_layer_size: usize = mip_sizes[..info.mipmap_count as usize].iter().sum();
+        let new_layer_size: usize = mip_sizes[..count as usize].iter().sum();
+
+        let data = self.deswizzled_data_ref()?;
+        let mut new_data = Vec::new();
+        for layer in 0..info.layer_count as usize {
+            let start = layer * old_layer_size;
+            let layer_data = &data[start..start + old_layer_size.min(new_layer_size)];
+            new_data.extend_from_slice(layer_data);
+            new_data.resize((layer + 1) * new_layer_size, 0u8);
+        }
+
+        let block_height = self.block_height_mip0();
         let data = swizzle_surface(
-            width as usize,
-            height as usize,
-            depth as usize,
-            data,
+            info.width as usize,
+            info.height as usize,
+            info.depth as usize,
+            &new_data,
             block_dim,
             Some(block_height),
             bytes_per_pixel,
-            mipmap_count as usize,
-            layer_count as usize,
+            count as usize,
+            info.layer_count as usize,
         )?;
 
-        let str_section = StrSection {
-            block_size: 0x58,
-            block_offset: 0x58,
-            strings: vec![BntxStr::from(name.to_owned())],
-        };
-
-        let str_section_size = str_section.get_size();
-        let dict_section_size = (DictSection {
-            node_count: 0,
-            nodes: vec![],
-        })
-        .get_size();
-
-        let mipmap_offsets = calculate_mipmap_offsets(
-            mipmap_count,
-            width,
+        let mut info = info.clone();
+        info.mipmap_count = count as u16;
+        info.mipmaps.mipmap_offsets = calculate_mipmap_offsets(
+            self.brtd_start() + BRTD_HEADER_SIZE,
+            count,
+            info.width,
             block_dim,
-            height,
-            depth,
+            info.height,
+            info.depth,
             block_height,
             bytes_per_pixel,
         );
+        self.replace_surface(info, data)
+    }
 
-        Ok(Self {
-            header: BntxHeader {
-                version: (0, 4),
-                bom: ByteOrder::LittleEndian,
-                inner: HeaderInner {
-                    revision: 0x400c,
-                    file_name: name.into(),
-                    str_section,
-                    reloc_table: RelocationTable {
-                        sections: vec![
-                            RelocationSection {
-                                pointer: 0,
-                                position: 0,
-                                size: (START_OF_STR_SECTION
-                                    + str_section_size
-                                    + dict_section_size
-                                    + SIZE_OF_BRTI
-                                    + 0x208) as u32,
-                                index: 0,
-                                count: 4,
-                            },
-                            RelocationSection {
-                                pointer: 0,
-                                position: BRTD_SECTION_START as u32,
-                                size: (data.len() + SIZE_OF_BRTD) as u32,
-                                index: 4,
-                                count: 1,
-                            },
-                        ],
-                        entries: vec![
-                            RelocationEntry {
-                                position: BNTX_HEADER_SIZE as u32 + 8,
-                                struct_count: 2,
-                                offset_count: 1,
-                                padding_count: (((HEADER_SIZE + MEM_POOL_SIZE)
-                                    - (BNTX_HEADER_SIZE + 0x10))
-                                    / 8) as u8,
-                            },
-                            RelocationEntry {
-                                position: BNTX_HEADER_SIZE as u32 + 0x18,
-                                struct_count: 2,
-                                offset_count: 2,
-                                padding_count: ((START_OF_STR_SECTION
-                                    + str_section_size
-                                    + dict_section_size
-                                    + 0x80
-                                    - HEADER_SIZE)
-                                    / 8) as u8,
-                            },
-                            RelocationEntry {
-                                position: (START_OF_STR_SECTION + str_section_size + 0x10) as u32,
-                                struct_count: 2,
-                                offset_count: 1,
-                                padding_count: 1,
-                            },
-                            RelocationEntry {
-                                position: (START_OF_STR_SECTION
-                                    + str_section_size
-                                    + dict_section_size
-                                    + 0x60) as u32,
-                                struct_count: 1,
-                                offset_count: 3,
-                                padding_count: 0,
-                            },
-                            RelocationEntry {
-                                position: (BNTX_HEADER_SIZE + 0x10) as u32,
-                                struct_count: 2,
-                                offset_count: 1,
-                                padding_count: (((START_OF_STR_SECTION
-                                    + str_section_size
-                                    + dict_section_size
-                                    + SIZE_OF_BRTI
-                                    + 0x200)
-                                    - (BNTX_HEADER_SIZE + 0x18))
-                                    / 8) as u8,
-                            },
-                        ],
-                    },
-                },
-            },
-            nx_header: NxHeader {
-                dict: DictSection {
-                    node_count: 0,
-                    nodes: vec![],
-                },
-                dict_size: 0x58,
-                brti: Brti {
-                    size: 3576,
-                    size2: 3576,
-                    flags: 1,
-                    texture_dimension: TextureDimension::D2,
-                    tile_mode: 0,
-                    swizzle: 0,
-                    mipmap_count: mipmap_count as u16,
-                    multi_sample_count: 1,
-                    format,
-                    unk2: 32,
-                    width,
-                    height,
-                    depth,
-                    layer_count,
-                    block_height_log2,
-                    unk4: [65543, 0, 0, 0, 0, 0],
-                    image_size: data.len() as _,
-                    align: 512,
-                    comp_sel: 84148994,
-                    texture_view_dimension: TextureViewDimension::D2,
-                    name_addr: name.to_owned().into(),
-                    parent_addr: 32,
-                    mipmaps: Mipmaps { mipmap_offsets },
-                    unk5: 0,
-                    unk6: 0,
-                    unk7: 0,
-                },
-                brtd: Brtd { image_data: data },
-            },
-        })
+    /// Replaces the swizzled image data and updates any fields that depend on the data.
+    ///
+    /// Returns [BntxError::OffsetOverflow] if the data does not fit in the u32 offsets used by the file.
+    fn set_swizzled_data(&mut self, data: Vec<u8>) -> Result<(), BntxError> {
+        let brti = self.nx_header.brti.clone();
+        self.replace_surface(brti, data)
     }
 
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, binrw::error::Error> {
-        let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
-        reader.read_le()
+    /// Replaces the BRTI and swizzled image data and updates any fields that depend on them.
+    /// The file is left unchanged if an error occurs.
+    fn replace_surface(&mut self, brti: Brti, data: Vec<u8>) -> Result<(), BntxError> {
+        let old_brti = std::mem::replace(&mut self.nx_header.brti, brti);
+        let old_data = std::mem::replace(&mut self.nx_header.brtd.image_data, data);
+
+        let data_len = self.nx_header.brtd.image_data.len();
+        let result = check_data_offsets(self.brtd_start() + BRTD_HEADER_SIZE, data_len)
+            .and_then(|_| u32_offset(data_len))
+            .and_then(|image_size| Ok((image_size, self.compute_relocation_table()?)));
+
+        match result {
+            Ok((image_size, reloc_table)) => {
+                self.nx_header.brti.image_size = image_size;
+                self.header.inner.reloc_table = reloc_table;
+                self.deswizzled_data = OnceLock::new();
+                Ok(())
+            }
+            Err(e) => {
+                self.nx_header.brti = old_brti;
+                self.nx_header.brtd.image_data = old_data;
+                Err(e)
+            }
+        }
     }
 
-    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), binrw::error::Error> {
-        let mut writer = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
-        self.write(&mut writer)
+    /// The position of the BRTD section based on the offset of the first mipmap.
+    fn brtd_start(&self) -> usize {
+        self.nx_header
+            .brti
+            .mipmaps
+            .mipmap_offsets
+            .first()
+            .map(|offset| (*offset as usize).saturating_sub(BRTD_HEADER_SIZE))
+            .unwrap_or(BRTD_SECTION_START)
     }
-}
 
-fn calculate_mipmap_offsets(
-    mipmap_count: u32,
-    width: u32,
-    block_dim: BlockDim,
-    height: u32,
-    depth: u32,
-    block_height: BlockHeight,
-    bytes_per_pixel: usize,
-) -> Vec<u64> {
-    let mut mipmap_offsets = Vec::new();
+    fn block_height_mip0(&self) -> BlockHeight {
+        BlockHeight::new(2u32.pow(self.nx_header.brti.block_height_log2) as usize).unwrap()
+    }
 
-    let mut mipmap_offset = 0;
-    for mip in 0..mipmap_count {
-        mipmap_offsets.push(START_OF_TEXTURE_DATA as u64 + mipmap_offset as u64);
+    /// The size in bytes of a single array layer including alignment padding.
+    fn layer_stride(&self) -> usize {
+        let info = &self.nx_header.brti;
+        let layer_count = (info.layer_count as usize).max(1);
+        swizzled_surface_size(
+            info.width as usize,
+            info.height as usize,
+            info.depth as usize,
+            info.format.block_dim(),
+            Some(self.block_height_mip0()),
+            info.format.bytes_per_pixel(),
+            info.mipmap_count as usize,
+            layer_count,
+        ) / layer_count
+    }
 
-        let mip_width = div_round_up((width as usize >> mip).max(1), block_dim.width.get());
-        let mip_height = div_round_up((height as usize >> mip).max(1), block_dim.height.get());
-        let mip_depth = div_round_up((depth as usize >> mip).max(1), block_dim.depth.get());
-        let mip_block_height = mip_block_height(mip_height, block_height);
-        let mip_size = tegra_swizzle::swizzle::swizzled_mip_size(
-            mip_width,
-            mip_height,
-            mip_depth,
-            mip_block_height,
-            bytes_per_pixel,
-        );
+    /// A hexdump of the header sections preceding the texture data
+    /// with offsets on the left and ASCII on the right for debugging.
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn dump_header_hex(&self) -> Result<String, BntxError> {
+        let mut writer = io::Cursor::new(Vec::new());
+        self.write(&mut writer)?;
+        let bytes = writer.into_inner();
 
-        mipmap_offset += mip_size;
-    }
-    mipmap_offsets
-}
+        let mut output = String::new();
+        let texture_data_start = self.brtd_start() + BRTD_HEADER_SIZE;
+        for (i, line) in bytes[..bytes.len().min(texture_data_start)]
+            .chunks(16)
+            .enumerate()
+        {
+            let mut hex = String::new();
+            for (j, byte) in line.iter().enumerate() {
+                if j == 8 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{byte:02x} "));
+            }
 
-#[derive(BinRead, PartialEq, Debug, Clone, Copy)]
-enum ByteOrder {
-    #[br(magic = 0xFFFEu16)]
-    LittleEndian,
-    #[br(magic = 0xFEFFu16)]
-    BigEndian,
-}
+            let ascii: String = line
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
 
-#[derive(BinRead, Debug)]
-#[br(magic = b"BNTX")]
-struct BntxHeader {
-    #[br(pad_before = 4)]
-    version: (u16, u16),
+            output.push_str(&format!("{:08x}  {hex:<49} |{ascii}|\n", i * 16));
+        }
+        Ok(output)
+    }
 
-    #[br(big)]
-    bom: ByteOrder,
+    /// Writes the file to `writer`.
+    ///
+    /// Returns an error without writing anything if the mipmap offsets are not strictly increasing.
+    pub fn write<W: io::Write + io::Seek>(&self, writer: &mut W) -> Result<(), BntxError> {
+        let endian = binrw::Endian::Little;
 
-    #[br(is_little = bom == ByteOrder::LittleEndian)]
-    inner: HeaderInner,
-}
+        let reloc_table = self.write_sections(writer, endian)?;
 
-impl BntxHeader {
-    fn write_options<W: io::Write + io::Seek>(
-        &self,
-        writer: &mut W,
-        options: binrw::Endian,
-        parent: &BntxFile,
-    ) -> Result<(), binrw::error::Error> {
-        let start_of_reloc_section =
-            (START_OF_TEXTURE_DATA + parent.nx_header.brtd.image_data.len()) as u32;
-        (
-            b"BNTX",
-            0u32,
-            self.version,
-            match self.bom {
-                ByteOrder::LittleEndian => b"\xFF\xFE",
-                ByteOrder::BigEndian => b"\xFE\xFF",
-            },
-            self.inner.revision,
-            FILENAME_STR_OFFSET as u32 + 2,
-            0u16,
-            START_OF_STR_SECTION as u16,
-            start_of_reloc_section,
-            start_of_reloc_section + (self.inner.reloc_table.get_size() as u32),
-        )
-            .write_options(writer, options, ())
+        let reloc_table_start = writer.stream_position()?;
+        reloc_table.write_options(writer, endian, ())?;
+        let file_size = writer.stream_position()?;
+
+        // The file size is stored as a u32 and must not be truncated.
+        write_at(
+            writer,
+            RELOC_TABLE_PTR_OFFSET,
+            &(
+                u32_offset(reloc_table_start as usize)?,
+                u32_offset(file_size as usize)?,
+            ),
+            endian,
+        )?;
+        writer.seek(SeekFrom::Start(file_size))?;
+
+        Ok(())
     }
-}
 
-#[binread]
-#[derive(Debug)]
-struct HeaderInner {
-    revision: u16,
+    /// Writes all sections except the relocation table.
+    /// Returns the relocation table for the positions of all pointers written.
+    fn write_sections<W: io::Write + io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+    ) -> Result<RelocationTable, BntxError> {
+        // Pointers to the header sections and pointers to the image data
+        // are stored in separate relocation sections.
+        // Related pointers are grouped so that each group can share entries.
+        let mut header_pointers = Vec::new();
+        let mut data_pointers = Vec::new();
 
-    #[br(parse_with = read_string_pointer)]
-    file_name: String,
+        // Each mipmap is stored after the previous mipmap in the image data.
+        let mipmap_offsets = &self.nx_header.brti.mipmaps.mipmap_offsets;
+        if let Some(i) = mipmap_offsets.windows(2).position(|w| w[0] >= w[1]) {
+            return Err(BntxError::UnorderedMipmapOffsets {
+                mip: i as u32 + 1,
+                offset: mipmap_offsets[i + 1],
+                previous: mipmap_offsets[i],
+            });
+        }
 
-    #[br(pad_before = 2, parse_with = FilePtr16::parse)]
-    str_section: StrSection,
+        // The first pass writes placeholders for offsets to later sections.
+        // The second pass fills in these offsets using the actual section positions.
+        self.header.write_options(writer, endian)?;
 
-    // Points to close to the end of the file.
-    // TODO: Does this mean we need at least two passes for offset calculation (size pass -> offset pass)?
-    #[br(parse_with = FilePtr32::parse)]
-    reloc_table: RelocationTable,
+        let nx_header_start = writer.stream_position()?;
+        self.nx_header.write_options(writer, endian, self)?;
+        let mut brti_ptr_pointers = vec![nx_header_start + 0x8];
+        let mut section_pointers = vec![nx_header_start + 0x18, nx_header_start + 0x20];
+        let mut brtd_pointers = vec![nx_header_start + 0x10];
 
-    #[br(temp)]
-    file_size: u32,
-}
+        // The memory pool is only filled in by the game at runtime.
+        [0u8; MEM_POOL_SIZE][..].write_options(writer, endian, ())?;
 
-fn read_string_pointer<'a, R: Read + Seek>(
-    reader: &mut R,
-    endian: binrw::Endian,
-    args: <FilePtr32<NullString> as BinRead>::Args<'a>,
-) -> BinResult<String> {
-    FilePtr32::<NullString>::parse(reader, endian, args).map(|s| s.to_string())
-}
+        // The NX header points to this pointer to the BRTI.
+        // Both pointers are filled in after writing the BRTI.
+        let brti_ptr_offset = writer.stream_position()?;
+        brti_ptr_pointers.push(brti_ptr_offset);
+        header_pointers.push(brti_ptr_pointers);
+        0u64.write_options(writer, endian, ())?;
 
-#[derive(BinRead, BinWrite, Debug)]
-struct RelocationSection {
-    pointer: u64,
-    position: u32,
-    size: u32,
-    index: u32,
-    count: u32,
-}
+        // Offsets to strings are calculated assuming a fixed string section position.
+        let str_start = writer.stream_position()?;
+        if str_start != START_OF_STR_SECTION as u64 {
+            return Err(BntxError::UnexpectedStrSectionStart {
+                expected: START_OF_STR_SECTION as u64,
+                actual: str_start,
+            });
+        }
+        self.header
+            .inner
+            .str_section
+            .write_options(writer, endian, ())?;
 
-const SIZE_OF_RELOC_SECTION: usize = size_of::<u64>() + (size_of::<u32>() * 4);
+        let dict_start = writer.stream_position()?;
+        self.nx_header.dict.write_options(
+            writer,
+            endian,
+            (&self.header.inner.str_section, START_OF_STR_SECTION as u64),
+        )?;
+        let dict_pointers = (0..self.nx_header.dict.nodes.len() as u64)
+            .map(|i| dict_start + DICT_NODES_OFFSET + i * SIZE_OF_DICT_NODE as u64 + 8)
+            .collect();
 
-#[derive(BinRead, BinWrite, Debug)]
-struct RelocationEntry {
-    position: u32,
-    struct_count: u16,
-    offset_count: u8,
-    padding_count: u8,
-}
+        // The NX header section pointers and BRTI runtime pointers have the same layout.
+        let brti_start = writer.stream_position()?;
+        self.nx_header.brti.write_options(writer, endian, self)?;
+        section_pointers.extend(BRTI_RUNTIME_POINTER_OFFSETS.iter().map(|o| brti_start + o));
+        header_pointers.push(section_pointers);
+        header_pointers.push(dict_pointers);
+        header_pointers.push(
+            BRTI_POINTER_OFFSETS
+                .iter()
+                .map(|o| brti_start + o)
+                .collect(),
+        );
 
-const SIZE_OF_RELOC_ENTRY: usize = size_of::<u32>() + size_of::<u16>() + (size_of::<u8>() * 2);
+        vec![0u8; MIPMAP_OFFSET_ARRAY_PADDING].write_options(writer, endian, ())?;
 
-#[binrw]
-#[derive(Debug)]
-#[brw(magic = b"_RLT")]
-#[bw(stream = w)]
-struct RelocationTable {
-    #[br(temp)]
-    #[bw(calc = w.stream_position().unwrap() as u32 - 4)]
-    rlt_section_pos: u32,
+        let mipmap_offsets_start = writer.stream_position()?;
+        for offset in &self.nx_header.brti.mipmaps.mipmap_offsets {
+            brtd_pointers.push(writer.stream_position()?);
+            offset.write_options(writer, endian, ())?;
+        }
+        data_pointers.push(brtd_pointers);
+        let mipmap_offsets_end = writer.stream_position()?;
 
-    #[br(temp)]
-    #[bw(calc = sections.len() as u32)]
-    count: u32,
+        // The BRTD has a fixed position, so the previous sections must not overlap it.
+        let brtd_start = self.brtd_start() as u64;
+        let padding_size =
+            brtd_start
+                .checked_sub(mipmap_offsets_end)
+                .ok_or(BntxError::HeaderTooLarge {
+                    size: mipmap_offsets_end as usize,
+                    max: brtd_start as usize,
+                })?;
+        vec![0u8; padding_size as usize].write_options(writer, endian, ())?;
 
-    #[br(pad_before = 4, count = count)]
-    #[bw(pad_before = 4)]
-    sections: Vec<RelocationSection>,
+        let brtd_start = writer.stream_position()?;
+        self.nx_header.brtd.write_options(writer, endian, ())?;
+        let brtd_end = writer.stream_position()?;
 
-    #[br(count = sections.iter().map(|x| x.count).sum::<u32>())]
-    entries: Vec<RelocationEntry>,
-}
+        write_at(writer, BRTI_PTR_PTR_OFFSET, &brti_ptr_offset, endian)?;
+        write_at(writer, brti_ptr_offset, &brti_start, endian)?;
+        write_at(writer, BRTD_PTR_OFFSET, &brtd_start, endian)?;
+        write_at(
+            writer,
+            brti_start + BRTI_MIPMAPS_PTR_OFFSET,
+            &mipmap_offsets_start,
+            endian,
+        )?;
+        writer.seek(SeekFrom::Start(brtd_end))?;
 
-use core::mem::size_of;
+        // The first section covers every section containing pointers
+        // from the start of the file through the end of the mipmap offsets.
+        RelocationTable::from_pointers(&[
+            (0, mipmap_offsets_end, header_pointers),
+            (brtd_start, brtd_end - brtd_start, data_pointers),
+        ])
+    }
 
-impl RelocationTable {
-    fn get_size(&self) -> usize {
-        b"_RLT".len()
-            + size_of::<u32>()
-            + size_of::<u32>()
-            + size_of::<u32>()
-            + (self.sections.len() * SIZE_OF_RELOC_SECTION)
-            + (self.entries.len() * SIZE_OF_RELOC_ENTRY)
+    /// The absolute offset of the texture name string in the written file.
+    /// The offset points to the string length before the characters.
+    pub fn name_offset_in_file(&self) -> usize {
+        self.header
+            .inner
+            .str_section
+            .name_offset(&self.nx_header.brti.name_addr.chars)
     }
-}
 
-#[binrw]
-#[derive(Debug)]
-#[brw(magic = b"_STR")]
-struct StrSection {
-    block_size: u32,
-    block_offset: u64,
+    /// The number of textures in the file from the NX header.
+    /// Only the first texture is currently supported.
+    pub fn texture_count(&self) -> u32 {
+        self.nx_header.count
+    }
 
-    #[br(temp)]
-    #[bw(calc = strings.len() as u32)]
-    str_count: u32,
+    /// Checks that the texture name in the string section and BRTI matches the file name.
+    /// Returns [BntxError::InconsistentName] for the first name that doesn't match.
+    pub fn validate_name_consistency(&self) -> Result<(), BntxError> {
+        let expected = &self.header.inner.file_name;
+        let names = [
+            (
+                "string section",
+                self.header.inner.str_section.strings.first(),
+            ),
+            ("BRTI", Some(&self.nx_header.brti.name_addr)),
+        ];
+        for (location, name) in names {
+            let actual = name.map(|n| n.chars.as_str()).unwrap_or_default();
+            if actual != expected {
+                return Err(BntxError::InconsistentName {
+                    location,
+                    expected: expected.clone(),
+                    actual: actual.to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
 
-    #[br(temp)]
-    #[bw(calc = BntxStr::from(String::new()))]
-    empty: BntxStr,
+    /// A short human readable summary of the texture for logging like
+    /// `"mario_hat: 512×512 BC7Srgb, 10 mips, 1 layer (234.5 KB)"`.
+    /// The size is the size of the swizzled image data.
+    pub fn describe(&self) -> String {
+        let info = &self.nx_header.brti;
+        let plural = |count: u32, name: &str| {
+            if count == 1 {
+                format!("{count} {name}")
+            } else {
+                format!("{count} {name}s")
+            }
+        };
 
-    #[br(count = str_count)]
-    #[bw(align_after = 8)]
-    strings: Vec<BntxStr>,
-}
+        let mut dimensions = format!("{}×{}", info.width, info.height);
+        if info.depth > 1 {
+            dimensions += &format!("×{}", info.depth);
+        }
 
-impl StrSection {
-    fn get_size(&self) -> usize {
-        align(
-            (5 * size_of::<u32>())
-                + EMPTY_STR_SIZE
-                + self.strings.iter().map(|x| x.get_size()).sum::<usize>(),
-            8,
+        format!(
+            "{}: {} {:?}, {}, {} ({:.1} KB)",
+            self.header.inner.file_name,
+            dimensions,
+            info.format,
+            plural(info.mipmap_count as u32, "mip"),
+            plural(info.layer_count, "layer"),
+            self.nx_header.brtd.image_data.len() as f64 / 1024.0
         )
     }
-}
-
-// TODO: These all refer to the string dict?
-#[binrw]
-#[derive(Debug)]
-struct BntxStr {
-    #[br(temp)]
-    #[bw(calc = chars.len() as u16)]
-    len: u16,
 
-    #[br(count = len, map = |x: Vec<u8>| String::from_utf8_lossy(&x).into_owned())]
-    #[bw(map = |s| s.as_bytes().to_vec())]
-    chars: String,
+    /// Copies the name, format, dimensions, and other settings from `other`
+    /// while keeping the existing swizzled image data.
+    ///
+    /// The size of the image data must match the swizzled size for the settings in `other`.
+    /// Returns [BntxError::DataSizeMismatch] otherwise.
+    /// Trailing zeros omitted from the image data are restored before comparing sizes.
+    ///
+    /// The file is left unchanged if an error occurs.
+    pub fn copy_header_from(&mut self, other: &BntxFile) -> Result<(), BntxError> {
+        let info = &other.nx_header.brti;
+        let expected = other.layer_stride() * (info.layer_count as usize).max(1);
+        let actual = self
+            .nx_header
+            .brtd
+            .image_data
+            .len()
+            .max(self.nx_header.brti.image_size as usize);
+        if info.mipmap_count > 0 && actual != expected {
+            return Err(BntxError::DataSizeMismatch { expected, actual });
+        }
 
-    #[br(align_after = 4, temp)]
-    #[bw(align_after = 4, calc = 0u8)]
-    null_terminator: u8,
-}
+        let old_header = std::mem::replace(&mut self.header, other.header.clone());
+        let old_nx_header = (
+            std::mem::replace(&mut self.nx_header.count, other.nx_header.count),
+            std::mem::replace(&mut self.nx_header.dict, other.nx_header.dict.clone()),
+            std::mem::replace(&mut self.nx_header.dict_size, other.nx_header.dict_size),
+        );
 
-fn align(x: usize, n: usize) -> usize {
-    (x + n - 1) & !(n - 1)
-}
+        // The BRTI and image data are restored by replace_surface if an error occurs.
+        let mut data = self.nx_header.brtd.image_data.clone();
+        data.resize(actual, 0);
+        let result = self.replace_surface(other.nx_header.brti.clone(), data);
+        if result.is_err() {
+            self.header = old_header;
+            (
+                self.nx_header.count,
+                self.nx_header.dict,
+                self.nx_header.dict_size,
+            ) = old_nx_header;
+        }
+        result
+    }
 
-impl BntxStr {
-    fn get_size(&self) -> usize {
-        align(size_of::<u16>() + self.chars.bytes().len() + 1, 4)
+    /// The total size in bytes of the file created by [BntxFile::write].
+    /// This can be used to preallocate the buffer for writing.
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn size_on_disk(&self) -> Result<usize, BntxError> {
+        let reloc_table = self.compute_relocation_table()?;
+        Ok(self.brtd_start()
+            + BRTD_HEADER_SIZE
+            + self.nx_header.brtd.image_data.len()
+            + reloc_table.get_size())
     }
-}
 
-impl From<String> for BntxStr {
-    fn from(chars: String) -> Self {
-        BntxStr { chars }
+    /// The [ConversionSupport](formats::ConversionSupport) for every pair of supported formats.
+    pub fn format_conversion_matrix(
+    ) -> HashMap<(SurfaceFormat, SurfaceFormat), formats::ConversionSupport> {
+        let formats = || {
+            formats::COMPRESSED_FORMATS
+                .iter()
+                .chain(formats::UNCOMPRESSED_FORMATS)
+                .copied()
+        };
+        formats()
+            .flat_map(|from| {
+                formats().map(move |to| ((from, to), formats::ConversionSupport::new(from, to)))
+            })
+            .collect()
     }
-}
 
-impl From<BntxStr> for String {
-    fn from(bntx_str: BntxStr) -> String {
-        bntx_str.chars
+    /// Derives the relocation table from the positions of all pointers in the written file.
+    /// This is the same table written by [BntxFile::write].
+    pub(crate) fn compute_relocation_table(&self) -> Result<RelocationTable, BntxError> {
+        // Count the bytes without copying any data.
+        let mut writer = SizeCounter::default();
+        let reloc_table = self.write_sections(&mut writer, binrw::Endian::Little)?;
+        Ok(reloc_table)
     }
-}
 
-// TODO: Rework this to write everything in a single pass.
-// TODO: is there a simple algorithm to calculate the absolute offsets?
-#[binread]
-#[derive(Debug)]
-#[br(magic = b"NX  ")]
-struct NxHeader {
-    #[br(temp)]
-    count: u32,
+    /// Describes each pointer in the stored relocation table
+    /// as `(offset, description)` like `(0x28, "NX header BRTI pointer")`.
+    /// For files read from disk, this is the table in the file rather than
+    /// the table that [BntxFile::write] would produce.
+    ///
+    /// This is intended for debugging the file layout.
+    pub fn read_relocation_table_entries(&self) -> Vec<(usize, &'static str)> {
+        let dict_start = (START_OF_STR_SECTION + self.header.inner.str_section.get_size()) as u64;
+        let brti_start = dict_start + self.nx_header.dict.get_size() as u64;
+        let mipmap_offsets_start = brti_start + (SIZE_OF_BRTI + MIPMAP_OFFSET_ARRAY_PADDING) as u64;
 
-    #[br(parse_with = read_double_indirect)]
-    brti: Brti,
+        let describe = |pos: u64| match pos {
+            BRTI_PTR_PTR_OFFSET => "NX header BRTI pointer",
+            BRTD_PTR_OFFSET => "NX header BRTD pointer",
+            NX_DICT_PTR_OFFSET => "NX header dictionary pointer",
+            NX_MEM_POOL_PTR_OFFSET => "NX header memory pool pointer",
+            p if p == START_OF_STR_SECTION as u64 - 8 => "BRTI pointer",
+            p if (dict_start..brti_start).contains(&p) => "dictionary node name pointer",
+            p if p == brti_start + BRTI_POINTER_OFFSETS[0] => "BRTI name pointer",
+            p if p == brti_start + BRTI_POINTER_OFFSETS[1] => "BRTI parent pointer",
+            p if p == brti_start + BRTI_MIPMAPS_PTR_OFFSET => "BRTI mipmap offsets pointer",
+            p if (brti_start..brti_start + SIZE_OF_BRTI as u64).contains(&p) => {
+                "BRTI unknown pointer"
+            }
+            p if p >= mipmap_offsets_start => "mipmap offset",
+            _ => "unknown pointer",
+        };
 
-    #[br(parse_with = FilePtr64::parse)]
-    brtd: Brtd,
+        self.header
+            .inner
+            .reloc_table
+            .entries
+            .iter()
+            .flat_map(RelocationEntry::pointers)
+            .map(|pos| (pos as usize, describe(pos)))
+            .collect()
+    }
 
-    #[br(parse_with = FilePtr64::parse)]
-    dict: DictSection,
-    dict_size: u64, // TODO: How to calculate this
-                    // 136 bytes of padding
-}
+    /// Writes the file to `writer` like [BntxFile::write]
+    /// and calls `progress` with the bytes written and total bytes.
+    ///
+    /// Progress is reported after writing the data for each mipmap of each array layer
+    /// and after writing the entire file.
+    pub fn write_with_progress<W, F>(&self, writer: &mut W, progress: F) -> Result<(), BntxError>
+    where
+        W: io::Write + io::Seek,
+        F: Fn(u64, u64),
+    {
+        let total = self.size_on_disk()? as u64;
 
-impl NxHeader {
-    fn write_options<W: io::Write + io::Seek>(
+        // The end of each mipmap is the start of the next mipmap.
+        let texture_data_start = (self.brtd_start() + BRTD_HEADER_SIZE) as u64;
+        let data_end = texture_data_start + self.nx_header.brtd.image_data.len() as u64;
+        let stride = self.layer_stride() as u64;
+        let mut boundaries: Vec<_> = (0..self.nx_header.brti.layer_count as u64)
+            .flat_map(|layer| {
+                self.nx_header
+                    .brti
+                    .mipmaps
+                    .mipmap_offsets
+                    .iter()
+                    .skip(1)
+                    .copied()
+                    .chain(std::iter::once(texture_data_start + stride))
+                    .map(move |offset| offset + layer * stride)
+            })
+            .filter(|offset| *offset < data_end)
+            .chain([data_end, total])
+            .collect();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut writer = ProgressWriter {
+            inner: writer,
+            position: 0,
+            boundaries,
+            next: 0,
+            total,
+            progress,
+        };
+        self.write(&mut writer)?;
+        Ok(())
+    }
+
+    /// Writes only the BRTD section containing the swizzled image data.
+    /// This is the same data written at the end of [BntxFile::write]
+    /// for loaders that store the texture data in a separate file.
+    pub fn write_brtd_only<W: io::Write + io::Seek>(
         &self,
         writer: &mut W,
-        options: binrw::Endian,
-        parent: &BntxFile,
-    ) -> Result<(), binrw::error::Error> {
-        (
-            b"NX  ",
-            1u32, // count
-            (HEADER_SIZE + MEM_POOL_SIZE) as u64,
-            BRTD_SECTION_START as u64,
-            (START_OF_STR_SECTION + parent.header.inner.str_section.get_size()) as u64,
-            self.dict_size,
-        )
-            .write_options(writer, options, ())
+    ) -> Result<(), BntxError> {
+        self.nx_header
+            .brtd
+            .write_options(writer, binrw::Endian::Little, ())?;
+        Ok(())
     }
-}
-
-#[derive(BinRead, Debug)]
-#[br(magic = b"_DIC")]
-struct DictSection {
-    node_count: u32,
-    // TODO: some sort of root node is always included?
-    #[br(count = node_count + 1)]
-    nodes: Vec<DictNode>,
-}
 
-#[derive(Debug, BinRead)]
-struct DictNode {
-    reference: i32,
-    left_index: u16,
-    right_index: u16,
-    #[br(parse_with = FilePtr64::parse)]
-    name: BntxStr,
-}
+    /// Create a [BntxFile] from `img` with a single mipmap.
+    ///
+    /// The format is selected based on the color type of `img`.
+    /// Grayscale images use [SurfaceFormat::R8Unorm] or [SurfaceFormat::R8G8Unorm] for images with alpha.
+    /// 16-bit grayscale images are converted to 8 bits per channel.
+    /// This loses precision but preserves the number of channels.
+    /// Other 16-bit images are scaled to the range 0.0 to 1.0
+    /// and use [SurfaceFormat::R16G16B16A16Sfloat].
+    /// 32-bit floating point images are converted to half precision
+    /// and use [SurfaceFormat::R16G16B16A16Sfloat] to preserve HDR values.
+    /// RGB images use an alpha of 1.0.
+    /// All remaining images are converted to RGBA and use [SurfaceFormat::R8G8B8A8Srgb].
+    pub fn from_image(img: image::DynamicImage, name: &str) -> Result<Self, BntxError> {
+        Self::from_image_with_rgba_format(img, name, SurfaceFormat::R8G8B8A8Srgb)
+    }
 
-// TODO: Derive binwrite instead.
-static DICT_SECTION: &[u8] = b"\x5F\x44\x49\x43\x01\x00\x00\x00\xFF\xFF\xFF\xFF\x01\x00\x00\x00\xB4\x01\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x01\x00\xB8\x01\x00\x00\x00\x00\x00\x00";
+    /// Create a [BntxFile] from the image file at `path` like [BntxFile::from_image].
+    ///
+    /// PNG files tagged as linear with a gamma of 1.0 use [SurfaceFormat::R8G8B8A8Unorm].
+    /// Other images are assumed to be sRGB and use [SurfaceFormat::R8G8B8A8Srgb].
+    pub fn from_image_file<P: AsRef<Path>>(path: P, name: &str) -> Result<Self, BntxError> {
+        let path = path.as_ref();
+        let img = image::open(path)?;
 
-impl DictSection {
-    fn get_size(&self) -> usize {
-        DICT_SECTION.len()
+        let format = if is_linear_png(path) {
+            SurfaceFormat::R8G8B8A8Unorm
+        } else {
+            SurfaceFormat::R8G8B8A8Srgb
+        };
+        Self::from_image_with_rgba_format(img, name, format)
     }
-}
 
-impl BinWrite for DictSection {
-    type Args<'a> = ();
+    fn from_image_with_rgba_format(
+        img: image::DynamicImage,
+        name: &str,
+        rgba_format: SurfaceFormat,
+    ) -> Result<Self, BntxError> {
+        let (format, data) = match img {
+            image::DynamicImage::ImageLuma8(_) | image::DynamicImage::ImageLuma16(_) => {
+                (SurfaceFormat::R8Unorm, img.to_luma8().into_raw())
+            }
+            image::DynamicImage::ImageLumaA8(_) | image::DynamicImage::ImageLumaA16(_) => {
+                (SurfaceFormat::R8G8Unorm, img.to_luma_alpha8().into_raw())
+            }
+            image::DynamicImage::ImageRgb16(_)
+            | image::DynamicImage::ImageRgba16(_)
+            | image::DynamicImage::ImageRgb32F(_)
+            | image::DynamicImage::ImageRgba32F(_) => (
+                SurfaceFormat::R16G16B16A16Sfloat,
+                img.to_rgba32f()
+                    .into_raw()
+                    .into_iter()
+                    .flat_map(|f| half::f16::from_f32(f).to_le_bytes())
+                    .collect(),
+            ),
+            _ => (rgba_format, img.to_rgba8().into_raw()),
+        };
 
-    fn write_options<W: io::Write + Seek>(
-        &self,
-        writer: &mut W,
-        endian: binrw::Endian,
-        args: Self::Args<'_>,
-    ) -> BinResult<()> {
-        DICT_SECTION.write_options(writer, endian, args)
+        Self::from_image_data(name, img.width(), img.height(), 1, 1, 1, format, &data)
     }
-}
 
-// TODO: Are these flags?
-#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq)]
-#[brw(repr(u32))]
-pub enum SurfaceFormat {
-    R8Unorm = 0x0201,
-    R8G8B8A8Unorm = 0x0b01,
-    R8G8B8A8Srgb = 0x0b06,
-    B8G8R8A8Unorm = 0x0c01,
-    B8G8R8A8Srgb = 0x0c06,
-    BC1Unorm = 0x1a01,
-    BC1Srgb = 0x1a06,
-    BC2Unorm = 0x1b01,
-    BC2Srgb = 0x1b06,
-    BC3Unorm = 0x1c01,
-    BC3Srgb = 0x1c06,
-    BC4Unorm = 0x1d01,
-    BC4Snorm = 0x1d02,
-    BC5Unorm = 0x1e01,
-    BC5Snorm = 0x1e02,
-    BC6Sfloat = 0x1f05,
-    BC6Ufloat = 0x1f0a,
-    BC7Unorm = 0x2001,
-    BC7Srgb = 0x2006,
-    // TODO: Fill in other known formats.
+    /// Create a [BntxFile] from unswizzled image data.
+    /// See [BntxFileBuilder] for configuring additional settings.
+    ///
+    /// For block compressed formats, `data` contains whole blocks like 8 bytes
+    /// for each 4x4 block of BC1 rather than individual texels.
+    /// The swizzled output is padded to the swizzle tile size automatically.
+    ///
+    /// Textures without a `name` use the name `"texture"`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_image_data<'a>(
+        name: impl Into<Option<&'a str>>,
+        width: u32,
+        height: u32,
+        depth: u32,
+        mipmap_count: u32,
+        layer_count: u32,
+        format: SurfaceFormat,
+        data: &'a [u8],
+    ) -> Result<Self, BntxError> {
+        BntxFileBuilder::new(name, width, height, format, data)
+            .depth(depth)
+            .mipmap_count(mipmap_count)
+            .layer_count(layer_count)
+            .build()
+    }
+
+    /// Create a [BntxFile] from unswizzled image data like [BntxFile::from_image_data]
+    /// but with `data` as a slice of some plain data type like `u32` or `f32`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_typed_image_data<'a, T: bytemuck::Pod>(
+        name: impl Into<Option<&'a str>>,
+        width: u32,
+        height: u32,
+        depth: u32,
+        mipmap_count: u32,
+        layer_count: u32,
+        format: SurfaceFormat,
+        data: &'a [T],
+    ) -> Result<Self, BntxError> {
+        Self::from_image_data(
+            name,
+            width,
+            height,
+            depth,
+            mipmap_count,
+            layer_count,
+            format,
+            bytemuck::cast_slice(data),
+        )
+    }
+
+    /// Create a [BntxFile] from separate unswizzled data for each mipmap.
+    /// The data for each mipmap should contain all array layers in order.
+    ///
+    /// Returns [BntxError::InvalidMipmapSize] if the data for a mipmap
+    /// does not match the expected size for the dimensions and format.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_image_data_mipmaps(
+        name: &str,
+        width: u32,
+        height: u32,
+        depth: u32,
+        layer_count: u32,
+        format: SurfaceFormat,
+        mipmaps: &[&[u8]],
+    ) -> Result<Self, BntxError> {
+        let block_dim = format.block_dim();
+        let bytes_per_pixel = format.bytes_per_pixel();
+
+        let mut layer_mipmaps = Vec::new();
+        for (mip, mip_data) in mipmaps.iter().enumerate() {
+            let mip_size = deswizzled_mip_size(
+                div_round_up((width as usize >> mip).max(1), block_dim.width.get()),
+                div_round_up((height as usize >> mip).max(1), block_dim.height.get()),
+                div_round_up((depth as usize >> mip).max(1), block_dim.depth.get()),
+                bytes_per_pixel,
+            );
+            let expected = mip_size * layer_count as usize;
+            if mip_data.len() != expected {
+                return Err(BntxError::InvalidMipmapSize {
+                    mip: mip as u32,
+                    expected,
+                    actual: mip_data.len(),
+                });
+            }
+            layer_mipmaps.push(mip_data.chunks_exact(mip_size.max(1)));
+        }
+
+        // The combined surface stores all the mipmaps for each layer in order.
+        let mut data = Vec::new();
+        for _ in 0..layer_count {
+            for mip_layers in &mut layer_mipmaps {
+                data.extend_from_slice(mip_layers.next().unwrap_or_default());
+            }
+        }
+
+        Self::from_image_data(
+            name,
+            width,
+            height,
+            depth,
+            mipmaps.len() as u32,
+            layer_count,
+            format,
+            &data,
+        )
+    }
+
+    /// Create a [BntxFile] from unswizzled image data like [BntxFile::from_image_data]
+    /// but validate the length of `data` for the `format` before swizzling.
+    ///
+    /// Returns [BntxError::UnalignedDataLength] if `data` does not contain a whole number of blocks
+    /// and [BntxError::NotEnoughData] if `data` is too small for the specified dimensions.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_image_data_checked(
+        name: &str,
+        width: u32,
+        height: u32,
+        depth: u32,
+        mipmap_count: u32,
+        layer_count: u32,
+        format: SurfaceFormat,
+        data: &[u8],
+    ) -> Result<Self, BntxError> {
+        let block_size = format.bytes_per_pixel();
+        if !data.len().is_multiple_of(block_size) {
+            return Err(BntxError::UnalignedDataLength {
+                format,
+                len: data.len(),
+                block_size,
+            });
+        }
+
+        let expected =
+            unswizzled_surface_size(width, height, depth, format, mipmap_count, layer_count);
+        if data.len() < expected {
+            return Err(BntxError::NotEnoughData {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        Self::from_image_data(
+            name,
+            width,
+            height,
+            depth,
+            mipmap_count,
+            layer_count,
+            format,
+            data,
+        )
+    }
+
+    /// Reads the file at `path` using the byte order from the byte order mark.
+    ///
+    /// Returns [BntxError::InvalidMagic] if the file does not start with `b"BNTX"`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, BntxError> {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        Self::read_with_bom(&mut reader)
+    }
+
+    /// Reads the file from `data` using the byte order from the byte order mark.
+    ///
+    /// Returns [BntxError::InvalidMagic] if `data` does not start with `b"BNTX"`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, BntxError> {
+        Self::read_with_bom(&mut io::Cursor::new(data))
+    }
+
+    fn read_with_bom<R: Read + Seek>(reader: &mut R) -> Result<Self, BntxError> {
+        check_magic(reader)?;
+        Self::read_options(reader, binrw::Endian::Little, (None,)).map_err(Into::into)
+    }
+
+    /// Reads the file from `reader` as little endian regardless of the byte order mark.
+    /// This can be used to repair files with an incorrect byte order mark.
+    pub fn read_le<R: Read + Seek>(reader: &mut R) -> Result<Self, BntxError> {
+        check_magic(reader)?;
+        Self::read_options(
+            reader,
+            binrw::Endian::Little,
+            (Some(binrw::Endian::Little),),
+        )
+        .map_err(Into::into)
+    }
+
+    /// Reads the file from `reader` as big endian regardless of the byte order mark.
+    /// This can be used to repair files with an incorrect byte order mark.
+    pub fn read_be<R: Read + Seek>(reader: &mut R) -> Result<Self, BntxError> {
+        check_magic(reader)?;
+        Self::read_options(reader, binrw::Endian::Big, (Some(binrw::Endian::Big),))
+            .map_err(Into::into)
+    }
+
+    /// Returns `true` if `reader` starts with the `b"BNTX"` magic
+    /// without parsing the rest of the file.
+    /// The reader is returned to its original position.
+    /// Inputs shorter than the magic return `false`.
+    pub fn verify_magic_bytes<R: Read + Seek>(reader: &mut R) -> Result<bool, BntxError> {
+        let start = reader.stream_position()?;
+        let mut magic = [0u8; 4];
+        let result = reader.read_exact(&mut magic);
+        reader.seek(SeekFrom::Start(start))?;
+
+        match result {
+            Ok(()) => Ok(&magic == b"BNTX"),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the file to a temporary file in the same directory as `path`
+    /// and renames it to `path` once writing succeeds.
+    /// This avoids leaving a partially written file at `path` if writing fails.
+    ///
+    /// Each call uses a unique temporary file,
+    /// so concurrent writes to the same `path` do not interfere with each other.
+    pub fn write_to_file_atomic<P: AsRef<Path>>(&self, path: P) -> Result<(), BntxError> {
+        static TEMP_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = path.as_ref();
+        let (temp_path, file) = loop {
+            let mut temp_name = std::ffi::OsString::from(".");
+            temp_name.push(path.file_name().unwrap_or_default());
+            temp_name.push(format!(
+                ".{}.{}.tmp",
+                std::process::id(),
+                TEMP_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let temp_path = path.with_file_name(temp_name);
+
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)
+            {
+                Ok(file) => break (temp_path, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let result = (|| {
+            let mut writer = std::io::BufWriter::new(file);
+            self.write(&mut writer)?;
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            std::fs::rename(&temp_path, path)?;
+            Ok(())
+        })();
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), BntxError> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut writer)
+    }
+
+    /// Writes the file to a new buffer like [BntxFile::write].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BntxError> {
+        let mut writer = io::Cursor::new(Vec::new());
+        self.write(&mut writer)?;
+        Ok(writer.into_inner())
+    }
+}
+
+/// Returns [BntxError::InvalidMagic] if `reader` does not start with `b"BNTX"`.
+/// The reader is returned to its original position.
+fn check_magic<R: Read + Seek>(reader: &mut R) -> Result<(), BntxError> {
+    let start = reader.stream_position()?;
+    let mut magic = Vec::new();
+    reader.by_ref().take(4).read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    if magic != b"BNTX" {
+        return Err(BntxError::InvalidMagic {
+            expected: b"BNTX",
+            got: magic,
+        });
+    }
+    Ok(())
+}
+
+/// The alignment in bytes for a swizzled surface with the given mip 0 block height.
+/// Surfaces start on a block boundary, so this is at least the size of a single GOB.
+fn surface_alignment(block_height: BlockHeight) -> u32 {
+    GOB_SIZE_IN_BYTES * block_height as u32
+}
+
+/// A 2D block of `width` x `height` texels.
+/// tegra_swizzle only provides constructors for 1x1 and 4x4 blocks.
+fn block_dim_2d(width: usize, height: usize) -> BlockDim {
+    BlockDim {
+        width: NonZeroUsize::new(width).unwrap(),
+        height: NonZeroUsize::new(height).unwrap(),
+        depth: NonZeroUsize::new(1).unwrap(),
+    }
+}
+
+fn is_linear_png(path: &Path) -> bool {
+    // The sRGB chunk takes priority over the gamma chunk if both are present.
+    std::fs::File::open(path)
+        .ok()
+        .and_then(|file| {
+            png::Decoder::new(std::io::BufReader::new(file))
+                .read_info()
+                .ok()
+        })
+        .map(|reader| {
+            let info = reader.info();
+            info.srgb.is_none() && info.source_gamma == Some(png::ScaledFloat::from_scaled(100_000))
+        })
+        .unwrap_or(false)
+}
+
+fn unswizzled_surface_size(
+    width: u32,
+    height: u32,
+    depth: u32,
+    format: SurfaceFormat,
+    mipmap_count: u32,
+    layer_count: u32,
+) -> usize {
+    deswizzled_surface_size(
+        width as usize,
+        height as usize,
+        depth as usize,
+        format.block_dim(),
+        format.bytes_per_pixel(),
+        mipmap_count as usize,
+        layer_count as usize,
+    )
+}
+
+fn max_mipmap_count(width: u32, height: u32, depth: u32) -> u32 {
+    // Each mipmap halves the dimensions until reaching 1x1x1.
+    let max_dimension = width.max(height).max(depth).max(1);
+    u32::BITS - max_dimension.leading_zeros()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn calculate_mipmap_offsets(
+    texture_data_start: usize,
+    mipmap_count: u32,
+    width: u32,
+    block_dim: BlockDim,
+    height: u32,
+    depth: u32,
+    block_height: BlockHeight,
+    bytes_per_pixel: usize,
+) -> Vec<u64> {
+    let mut mipmap_offsets = Vec::new();
+
+    let mut mipmap_offset = 0;
+    for mip in 0..mipmap_count {
+        mipmap_offsets.push((texture_data_start + mipmap_offset) as u64);
+
+        let mip_width = div_round_up((width as usize >> mip).max(1), block_dim.width.get());
+        let mip_height = div_round_up((height as usize >> mip).max(1), block_dim.height.get());
+        let mip_depth = div_round_up((depth as usize >> mip).max(1), block_dim.depth.get());
+        let mip_block_height = mip_block_height(mip_height, block_height);
+        let mip_size = tegra_swizzle::swizzle::swizzled_mip_size(
+            mip_width,
+            mip_height,
+            mip_depth,
+            mip_block_height,
+            bytes_per_pixel,
+        );
+
+        mipmap_offset += mip_size;
+    }
+    mipmap_offsets
+}
+
+#[derive(BinRead, PartialEq, Debug, Clone, Copy)]
+enum ByteOrder {
+    #[br(magic = 0xFFFEu16)]
+    LittleEndian,
+    #[br(magic = 0xFEFFu16)]
+    BigEndian,
+}
+
+fn is_little_endian(endian: Option<binrw::Endian>, bom: ByteOrder) -> bool {
+    match endian {
+        Some(endian) => endian == binrw::Endian::Little,
+        None => bom == ByteOrder::LittleEndian,
+    }
+}
+
+// The endian argument overrides the byte order mark when reading.
+#[derive(BinRead, Debug, Clone)]
+#[br(magic = b"BNTX", import(endian: Option<binrw::Endian>))]
+struct BntxHeader {
+    #[br(pad_before = 4)]
+    version: (u16, u16),
+
+    #[br(big)]
+    bom: ByteOrder,
+
+    #[br(is_little = is_little_endian(endian, bom))]
+    inner: HeaderInner,
+}
+
+impl BntxHeader {
+    fn write_options<W: io::Write + io::Seek>(
+        &self,
+        writer: &mut W,
+        options: binrw::Endian,
+    ) -> Result<(), binrw::error::Error> {
+        (
+            b"BNTX",
+            0u32,
+            self.version,
+            match self.bom {
+                ByteOrder::LittleEndian => b"\xFF\xFE",
+                ByteOrder::BigEndian => b"\xFE\xFF",
+            },
+            self.inner.revision,
+            // Point to the characters after the string length.
+            self.inner.str_section.name_offset(&self.inner.file_name) as u32 + 2,
+            0u16,
+            START_OF_STR_SECTION as u16,
+            // The relocation table offset and file size are filled in after writing.
+            0u32,
+            0u32,
+        )
+            .write_options(writer, options, ())
+    }
+}
+
+/// The offset of the relocation table offset and file size in the BNTX header.
+const RELOC_TABLE_PTR_OFFSET: u64 = 0x18;
+
+/// The offset of the BRTD pointer in the NX header.
+const BRTD_PTR_OFFSET: u64 = BNTX_HEADER_SIZE as u64 + 0x10;
+
+/// The offset of the pointer to the BRTI pointer in the NX header.
+const BRTI_PTR_PTR_OFFSET: u64 = BNTX_HEADER_SIZE as u64 + 0x8;
+
+/// The offset of the dictionary pointer in the NX header.
+const NX_DICT_PTR_OFFSET: u64 = BNTX_HEADER_SIZE as u64 + 0x18;
+
+/// The offset of the memory pool pointer in the NX header.
+const NX_MEM_POOL_PTR_OFFSET: u64 = BNTX_HEADER_SIZE as u64 + 0x20;
+
+/// The offset of the mipmap offsets pointer relative to the start of the BRTI.
+const BRTI_MIPMAPS_PTR_OFFSET: u64 = 0x70;
+
+/// The offsets of the name, parent, and mipmap offsets pointers relative to the start of the BRTI.
+const BRTI_POINTER_OFFSETS: [u64; 3] = [0x60, 0x68, BRTI_MIPMAPS_PTR_OFFSET];
+
+/// The offsets of the two unknown pointers to the space reserved after the BRTI
+/// relative to the start of the BRTI.
+const BRTI_RUNTIME_POINTER_OFFSETS: [u64; 2] = [0x80, 0x88];
+
+/// The offset of the first dictionary node relative to the start of the dictionary.
+const DICT_NODES_OFFSET: u64 = 8;
+
+/// A writer that only tracks the position and length of the written data.
+#[derive(Default)]
+struct SizeCounter {
+    position: u64,
+    len: u64,
+}
+
+impl io::Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.position += buf.len() as u64;
+        self.len = self.len.max(self.position);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for SizeCounter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position =
+            position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+        Ok(self.position)
+    }
+}
+
+/// A writer that reports progress after reaching each boundary position.
+struct ProgressWriter<'a, W, F> {
+    inner: &'a mut W,
+    position: u64,
+    boundaries: Vec<u64>,
+    next: usize,
+    total: u64,
+    progress: F,
+}
+
+impl<W: io::Write, F: Fn(u64, u64)> io::Write for ProgressWriter<'_, W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Split writes at the next boundary to report progress as soon as it is reached.
+        let mut len = buf.len();
+        if let Some(boundary) = self.boundaries.get(self.next) {
+            if *boundary > self.position && *boundary < self.position + len as u64 {
+                len = (boundary - self.position) as usize;
+            }
+        }
+
+        let written = self.inner.write(&buf[..len])?;
+        self.position += written as u64;
+
+        while let Some(boundary) = self.boundaries.get(self.next) {
+            if *boundary > self.position {
+                break;
+            }
+            (self.progress)(*boundary, self.total);
+            self.next += 1;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
-impl SurfaceFormat {
-    fn bytes_per_pixel(&self) -> usize {
-        match self {
-            SurfaceFormat::R8Unorm => 1,
-            SurfaceFormat::R8G8B8A8Unorm => 4,
-            SurfaceFormat::R8G8B8A8Srgb => 4,
-            SurfaceFormat::B8G8R8A8Unorm => 4,
-            SurfaceFormat::B8G8R8A8Srgb => 4,
-            SurfaceFormat::BC1Unorm => 8,
-            SurfaceFormat::BC1Srgb => 8,
-            SurfaceFormat::BC2Unorm => 16,
-            SurfaceFormat::BC2Srgb => 16,
-            SurfaceFormat::BC3Unorm => 16,
-            SurfaceFormat::BC3Srgb => 16,
-            SurfaceFormat::BC4Unorm => 8,
-            SurfaceFormat::BC4Snorm => 8,
-            SurfaceFormat::BC5Unorm => 16,
-            SurfaceFormat::BC5Snorm => 16,
-            SurfaceFormat::BC6Sfloat => 16,
-            SurfaceFormat::BC6Ufloat => 16,
-            SurfaceFormat::BC7Unorm => 16,
-            SurfaceFormat::BC7Srgb => 16,
-        }
+impl<W: io::Seek, F> io::Seek for ProgressWriter<'_, W, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// Writes `value` at `position` without changing the data at any other position.
+fn write_at<W, T>(
+    writer: &mut W,
+    position: u64,
+    value: &T,
+    endian: binrw::Endian,
+) -> Result<(), binrw::error::Error>
+where
+    W: io::Write + io::Seek,
+    T: BinWrite,
+    for<'a> T::Args<'a>: Default,
+{
+    writer.seek(SeekFrom::Start(position))?;
+    value.write_options(writer, endian, Default::default())
+}
+
+#[binread]
+#[derive(Debug, Clone)]
+struct HeaderInner {
+    revision: u16,
+
+    #[br(parse_with = read_string_pointer)]
+    file_name: String,
+
+    #[br(pad_before = 2, parse_with = FilePtr16::parse)]
+    str_section: StrSection,
+
+    // Points to close to the end of the file.
+    // This offset is only known after writing the image data.
+    #[br(parse_with = FilePtr32::parse)]
+    reloc_table: RelocationTable,
+
+    #[br(temp)]
+    file_size: u32,
+}
+
+fn read_string_pointer<'a, R: Read + Seek>(
+    reader: &mut R,
+    endian: binrw::Endian,
+    args: <FilePtr32<NullString> as BinRead>::Args<'a>,
+) -> BinResult<String> {
+    FilePtr32::<NullString>::parse(reader, endian, args).map(|s| s.to_string())
+}
+
+#[derive(BinRead, BinWrite, Debug, Clone)]
+struct RelocationSection {
+    pointer: u64,
+    position: u32,
+    size: u32,
+    index: u32,
+    count: u32,
+}
+
+const SIZE_OF_RELOC_SECTION: usize = size_of::<u64>() + (size_of::<u32>() * 4);
+
+#[derive(BinRead, BinWrite, Debug, Clone)]
+struct RelocationEntry {
+    position: u32,
+    struct_count: u16,
+    offset_count: u8,
+    padding_count: u8,
+}
+
+impl RelocationEntry {
+    /// Creates an entry for the longest possible prefix of the sorted `pointers`.
+    /// Returns the entry and the number of pointers it covers.
+    ///
+    /// The padding count is the number of 8 byte values between the end of one group
+    /// of consecutive pointers and the start of the next group.
+    fn from_pointers(pointers: &[u64]) -> Result<(Self, usize), BntxError> {
+        let start = pointers[0];
+
+        // Find the run of consecutive pointers.
+        let offset_count = pointers
+            .iter()
+            .enumerate()
+            .take(u8::MAX as usize)
+            .take_while(|(i, p)| **p == start + *i as u64 * 8)
+            .count();
+
+        // Find additional runs of the same length with the same spacing.
+        let mut struct_count = 1;
+        let mut padding_count = 0;
+        if let Some(next) = pointers.get(offset_count) {
+            let padding = (next - (start + offset_count as u64 * 8)) / 8;
+            if padding <= u8::MAX as u64 {
+                let stride = (offset_count as u64 + padding) * 8;
+                let is_run = |i: usize| {
+                    let run_start = start + i as u64 * stride;
+                    pointers
+                        .get(i * offset_count..(i + 1) * offset_count)
+                        .is_some_and(|run| {
+                            run.iter()
+                                .enumerate()
+                                .all(|(j, p)| *p == run_start + j as u64 * 8)
+                        })
+                };
+                while struct_count < u16::MAX as usize && is_run(struct_count) {
+                    struct_count += 1;
+                }
+                if struct_count > 1 {
+                    padding_count = padding as u8;
+                }
+            }
+        }
+
+        Ok((
+            Self {
+                position: u32_offset(start as usize)?,
+                struct_count: struct_count as u16,
+                offset_count: offset_count as u8,
+                padding_count,
+            },
+            struct_count * offset_count,
+        ))
+    }
+
+    /// The positions of all the pointers covered by this entry.
+    fn pointers(&self) -> impl Iterator<Item = u64> + '_ {
+        let stride = (self.offset_count as u64 + self.padding_count as u64) * 8;
+        (0..self.struct_count as u64).flat_map(move |i| {
+            (0..self.offset_count as u64).map(move |j| self.position as u64 + i * stride + j * 8)
+        })
+    }
+}
+
+const SIZE_OF_RELOC_ENTRY: usize = size_of::<u32>() + size_of::<u16>() + (size_of::<u8>() * 2);
+
+#[binrw]
+#[derive(Debug, Clone)]
+#[brw(magic = b"_RLT")]
+#[bw(stream = w)]
+struct RelocationTable {
+    #[br(temp)]
+    #[bw(calc = w.stream_position().unwrap() as u32 - 4)]
+    rlt_section_pos: u32,
+
+    #[br(temp)]
+    #[bw(calc = sections.len() as u32)]
+    count: u32,
+
+    #[br(pad_before = 4, count = count)]
+    #[bw(pad_before = 4)]
+    sections: Vec<RelocationSection>,
+
+    #[br(count = sections.iter().map(|x| x.count).sum::<u32>())]
+    entries: Vec<RelocationEntry>,
+}
+
+use core::mem::size_of;
+
+impl RelocationTable {
+    /// Creates a relocation table from `(position, size, pointer_groups)` for each section.
+    /// The pointers in each group are combined into entries covering consecutive pointers
+    /// and repeated groups of pointers with the same spacing.
+    /// Entries are added in the order of the groups.
+    fn from_pointers(sections: &[(u64, u64, Vec<Vec<u64>>)]) -> Result<Self, BntxError> {
+        let mut table = RelocationTable {
+            sections: Vec::new(),
+            entries: Vec::new(),
+        };
+
+        for (position, size, pointer_groups) in sections {
+            let index = table.entries.len() as u32;
+            for pointers in pointer_groups {
+                let mut pointers = pointers.clone();
+                pointers.sort_unstable();
+                pointers.dedup();
+
+                let mut remaining = &pointers[..];
+                while !remaining.is_empty() {
+                    let (entry, count) = RelocationEntry::from_pointers(remaining)?;
+                    table.entries.push(entry);
+                    remaining = &remaining[count..];
+                }
+            }
+
+            table.sections.push(RelocationSection {
+                pointer: 0,
+                position: u32_offset(*position as usize)?,
+                size: u32_offset(*size as usize)?,
+                index,
+                count: table.entries.len() as u32 - index,
+            });
+        }
+
+        Ok(table)
+    }
+
+    fn get_size(&self) -> usize {
+        b"_RLT".len()
+            + size_of::<u32>()
+            + size_of::<u32>()
+            + size_of::<u32>()
+            + (self.sections.len() * SIZE_OF_RELOC_SECTION)
+            + (self.entries.len() * SIZE_OF_RELOC_ENTRY)
+    }
+}
+
+#[binrw]
+#[derive(Debug, Clone)]
+#[brw(magic = b"_STR")]
+struct StrSection {
+    block_size: u32,
+    block_offset: u64,
+
+    #[br(temp)]
+    #[bw(calc = strings.len() as u32)]
+    str_count: u32,
+
+    #[br(temp)]
+    #[bw(calc = BntxStr::from(String::new()))]
+    empty: BntxStr,
+
+    #[br(count = str_count)]
+    #[bw(align_after = 8)]
+    strings: Vec<BntxStr>,
+}
+
+impl StrSection {
+    /// The absolute offset of the string matching `name`
+    /// if the section starts at `start`.
+    fn string_offset(&self, name: &str, start: u64) -> Option<u64> {
+        let empty_offset = start + STR_HEADER_SIZE as u64;
+        if name.is_empty() {
+            return Some(empty_offset);
+        }
+
+        let mut offset = empty_offset + EMPTY_STR_SIZE as u64;
+        for s in &self.strings {
+            if s.chars == name {
+                return Some(offset);
+            }
+            offset += s.get_size() as u64;
+        }
+        None
+    }
+
+    /// The absolute offset of the string matching `name` in the file.
+    /// Defaults to the first string if `name` is not in the section.
+    fn name_offset(&self, name: &str) -> usize {
+        let start = START_OF_STR_SECTION as u64;
+        self.string_offset(name, start)
+            .or_else(|| {
+                let first = self
+                    .strings
+                    .first()
+                    .map(|s| s.chars.as_str())
+                    .unwrap_or_default();
+                self.string_offset(first, start)
+            })
+            .unwrap_or(start + STR_HEADER_SIZE as u64) as usize
+    }
+
+    fn get_size(&self) -> usize {
+        align(
+            STR_HEADER_SIZE
+                + EMPTY_STR_SIZE
+                + self.strings.iter().map(|x| x.get_size()).sum::<usize>(),
+            8,
+        )
+    }
+}
+
+// TODO: These all refer to the string dict?
+#[binrw]
+#[derive(Debug, Clone)]
+struct BntxStr {
+    #[br(temp)]
+    #[bw(calc = chars.len() as u16)]
+    len: u16,
+
+    // binrw converts counts with usize::try_from, which clippy flags for u16.
+    #[br(count = u32::from(len), map = |x: Vec<u8>| String::from_utf8_lossy(&x).into_owned())]
+    #[bw(map = |s| s.as_bytes().to_vec())]
+    chars: String,
+
+    #[br(align_after = 4, temp)]
+    #[bw(align_after = 4, calc = 0u8)]
+    null_terminator: u8,
+}
+
+impl BntxStr {
+    fn get_size(&self) -> usize {
+        align(size_of::<u16>() + self.chars.len() + 1, 4)
+    }
+}
+
+impl From<String> for BntxStr {
+    fn from(chars: String) -> Self {
+        BntxStr { chars }
+    }
+}
+
+impl From<BntxStr> for String {
+    fn from(bntx_str: BntxStr) -> String {
+        bntx_str.chars
+    }
+}
+
+// TODO: Rework this to write everything in a single pass.
+// TODO: is there a simple algorithm to calculate the absolute offsets?
+#[binread]
+#[derive(Debug)]
+#[br(magic = b"NX  ")]
+struct NxHeader {
+    // The number of textures.
+    count: u32,
+
+    #[br(parse_with = read_double_indirect)]
+    brti: Brti,
+
+    #[br(parse_with = FilePtr64::parse)]
+    brtd: Brtd,
+
+    #[br(parse_with = FilePtr64::parse)]
+    dict: DictSection,
+    // The relocation table marks this field as a pointer,
+    // so this is the address of the memory pool rather than a size.
+    // The value does not depend on the number of dictionary entries.
+    dict_size: u64,
+    // 136 bytes of padding
+}
+
+impl NxHeader {
+    fn write_options<W: io::Write + io::Seek>(
+        &self,
+        writer: &mut W,
+        options: binrw::Endian,
+        parent: &BntxFile,
+    ) -> Result<(), binrw::error::Error> {
+        (
+            b"NX  ",
+            self.count,
+            // The BRTI and BRTD offsets are filled in after writing.
+            0u64,
+            0u64,
+            (START_OF_STR_SECTION + parent.header.inner.str_section.get_size()) as u64,
+            self.dict_size,
+        )
+            .write_options(writer, options, ())
+    }
+}
+
+#[derive(BinRead, Debug, Clone)]
+#[br(magic = b"_DIC")]
+struct DictSection {
+    node_count: u32,
+    // The root node is always included.
+    #[br(count = node_count + 1)]
+    nodes: Vec<DictNode>,
+}
+
+#[derive(Debug, Clone, BinRead)]
+struct DictNode {
+    reference: i32,
+    left_index: u16,
+    right_index: u16,
+    #[br(parse_with = FilePtr64::parse)]
+    name: BntxStr,
+}
+
+const SIZE_OF_DICT_NODE: usize = size_of::<i32>() + (size_of::<u16>() * 2) + size_of::<u64>();
+
+impl DictSection {
+    fn get_size(&self) -> usize {
+        b"_DIC".len() + size_of::<u32>() + self.nodes.len() * SIZE_OF_DICT_NODE
+    }
+}
+
+// The node names point to the strings in the string section starting at the given offset.
+impl BinWrite for DictSection {
+    type Args<'a> = (&'a StrSection, u64);
+
+    fn write_options<W: io::Write + Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        (str_section, str_section_start): Self::Args<'_>,
+    ) -> BinResult<()> {
+        (b"_DIC", self.node_count).write_options(writer, endian, ())?;
+        for node in &self.nodes {
+            let pos = writer.stream_position()?;
+            let name_offset = str_section
+                .string_offset(&node.name.chars, str_section_start)
+                .ok_or_else(|| binrw::Error::AssertFail {
+                    pos,
+                    message: format!(
+                        "dict name {:?} is not in the string section",
+                        node.name.chars
+                    ),
+                })?;
+            (
+                node.reference,
+                node.left_index,
+                node.right_index,
+                name_offset,
+            )
+                .write_options(writer, endian, ())?;
+        }
+        Ok(())
+    }
+}
+
+// TODO: Are these flags?
+#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq, Hash)]
+#[brw(repr(u32))]
+pub enum SurfaceFormat {
+    R8Unorm = 0x0201,
+    R32Sfloat = 0x0301,
+    R32Uint = 0x0303,
+    R32Sint = 0x0304,
+    R16Unorm = 0x0601,
+    R16Snorm = 0x0602,
+    R16Sfloat = 0x060d,
+    R8G8Unorm = 0x0701,
+    R8G8Snorm = 0x0702,
+    R16G16Unorm = 0x0901,
+    R16G16Snorm = 0x0902,
+    R16G16Sfloat = 0x090d,
+    R8G8B8A8Unorm = 0x0b01,
+    R8G8B8A8Srgb = 0x0b06,
+    B8G8R8A8Unorm = 0x0c01,
+    B8G8R8A8Srgb = 0x0c06,
+    R16G16B16A16Unorm = 0x0f01,
+    R16G16B16A16Snorm = 0x0f02,
+    R16G16B16A16Sfloat = 0x0f0d,
+    R10G10B10A2Unorm = 0x1201,
+    BC1Unorm = 0x1a01,
+    BC1Srgb = 0x1a06,
+    BC2Unorm = 0x1b01,
+    BC2Srgb = 0x1b06,
+    BC3Unorm = 0x1c01,
+    BC3Srgb = 0x1c06,
+    BC4Unorm = 0x1d01,
+    BC4Snorm = 0x1d02,
+    BC5Unorm = 0x1e01,
+    BC5Snorm = 0x1e02,
+    BC6Sfloat = 0x1f05,
+    BC6Ufloat = 0x1f0a,
+    BC7Unorm = 0x2001,
+    BC7Srgb = 0x2006,
+    Etc2Rgb8Unorm = 0x2601,
+    EacR11Unorm = 0x2701,
+    Astc4x4Unorm = 0x2d01,
+    Astc4x4Srgb = 0x2d06,
+    Astc6x6Unorm = 0x3101,
+    Astc6x6Srgb = 0x3106,
+    Astc8x8Unorm = 0x3401,
+    Astc8x8Srgb = 0x3406,
+    // TODO: Fill in other known formats.
+}
+
+impl TryFrom<u32> for SurfaceFormat {
+    type Error = BntxError;
+
+    /// Returns [BntxError::UnsupportedFormat] for unknown format values.
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Self::read_le(&mut io::Cursor::new(value.to_le_bytes()))
+            .map_err(|_| BntxError::UnsupportedFormat(value))
+    }
+}
+
+impl SurfaceFormat {
+    /// The size in bytes of a single block with dimensions [SurfaceFormat::block_dim].
+    ///
+    /// For uncompressed formats, this is the size of a single pixel.
+    /// For block compressed formats, this is the size of an entire block
+    /// and *not* the size of a texel like 8 bytes for each 4x4 block of BC1.
+    fn bytes_per_pixel(&self) -> usize {
+        match self {
+            SurfaceFormat::R8Unorm => 1,
+            SurfaceFormat::R32Sfloat => 4,
+            SurfaceFormat::R32Uint => 4,
+            SurfaceFormat::R32Sint => 4,
+            SurfaceFormat::R16Unorm => 2,
+            SurfaceFormat::R16Snorm => 2,
+            SurfaceFormat::R16Sfloat => 2,
+            SurfaceFormat::R8G8Unorm => 2,
+            SurfaceFormat::R8G8Snorm => 2,
+            SurfaceFormat::R16G16Unorm => 4,
+            SurfaceFormat::R16G16Snorm => 4,
+            SurfaceFormat::R16G16Sfloat => 4,
+            SurfaceFormat::R8G8B8A8Unorm => 4,
+            SurfaceFormat::R8G8B8A8Srgb => 4,
+            SurfaceFormat::B8G8R8A8Unorm => 4,
+            SurfaceFormat::B8G8R8A8Srgb => 4,
+            SurfaceFormat::R16G16B16A16Unorm => 8,
+            SurfaceFormat::R16G16B16A16Snorm => 8,
+            SurfaceFormat::R16G16B16A16Sfloat => 8,
+            SurfaceFormat::R10G10B10A2Unorm => 4,
+            SurfaceFormat::BC1Unorm => 8,
+            SurfaceFormat::BC1Srgb => 8,
+            SurfaceFormat::BC2Unorm => 16,
+            SurfaceFormat::BC2Srgb => 16,
+            SurfaceFormat::BC3Unorm => 16,
+            SurfaceFormat::BC3Srgb => 16,
+            SurfaceFormat::BC4Unorm => 8,
+            SurfaceFormat::BC4Snorm => 8,
+            SurfaceFormat::BC5Unorm => 16,
+            SurfaceFormat::BC5Snorm => 16,
+            SurfaceFormat::BC6Sfloat => 16,
+            SurfaceFormat::BC6Ufloat => 16,
+            SurfaceFormat::BC7Unorm => 16,
+            SurfaceFormat::BC7Srgb => 16,
+            SurfaceFormat::Etc2Rgb8Unorm => 8,
+            SurfaceFormat::EacR11Unorm => 16,
+            SurfaceFormat::Astc4x4Unorm => 16,
+            SurfaceFormat::Astc4x4Srgb => 16,
+            SurfaceFormat::Astc6x6Unorm => 16,
+            SurfaceFormat::Astc6x6Srgb => 16,
+            SurfaceFormat::Astc8x8Unorm => 16,
+            SurfaceFormat::Astc8x8Srgb => 16,
+        }
+    }
+
+    /// The dimensions in texels of a single block.
+    /// Uncompressed formats use 1x1x1 blocks.
+    fn block_dim(&self) -> BlockDim {
+        match self {
+            SurfaceFormat::R8Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::R32Sfloat => BlockDim::uncompressed(),
+            SurfaceFormat::R32Uint => BlockDim::uncompressed(),
+            SurfaceFormat::R32Sint => BlockDim::uncompressed(),
+            SurfaceFormat::R16Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16Snorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16Sfloat => BlockDim::uncompressed(),
+            SurfaceFormat::R8G8Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::R8G8Snorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16G16Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16G16Snorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16G16Sfloat => BlockDim::uncompressed(),
+            SurfaceFormat::R8G8B8A8Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::R8G8B8A8Srgb => BlockDim::uncompressed(),
+            SurfaceFormat::B8G8R8A8Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::B8G8R8A8Srgb => BlockDim::uncompressed(),
+            SurfaceFormat::R16G16B16A16Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16G16B16A16Snorm => BlockDim::uncompressed(),
+            SurfaceFormat::R16G16B16A16Sfloat => BlockDim::uncompressed(),
+            SurfaceFormat::R10G10B10A2Unorm => BlockDim::uncompressed(),
+            SurfaceFormat::BC1Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC1Srgb => BlockDim::block_4x4(),
+            SurfaceFormat::BC2Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC2Srgb => BlockDim::block_4x4(),
+            SurfaceFormat::BC3Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC3Srgb => BlockDim::block_4x4(),
+            SurfaceFormat::BC4Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC4Snorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC5Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC5Snorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC6Sfloat => BlockDim::block_4x4(),
+            SurfaceFormat::BC6Ufloat => BlockDim::block_4x4(),
+            SurfaceFormat::BC7Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::BC7Srgb => BlockDim::block_4x4(),
+            SurfaceFormat::Etc2Rgb8Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::EacR11Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::Astc4x4Unorm => BlockDim::block_4x4(),
+            SurfaceFormat::Astc4x4Srgb => BlockDim::block_4x4(),
+            SurfaceFormat::Astc6x6Unorm => block_dim_2d(6, 6),
+            SurfaceFormat::Astc6x6Srgb => block_dim_2d(6, 6),
+            SurfaceFormat::Astc8x8Unorm => block_dim_2d(8, 8),
+            SurfaceFormat::Astc8x8Srgb => block_dim_2d(8, 8),
+        }
+    }
+
+    /// The number of color channels for each texel like 4 for RGBA formats.
+    /// Compressed formats return the channels of the decoded texels.
+    pub fn channel_count(&self) -> u32 {
+        match self {
+            SurfaceFormat::R8Unorm => 1,
+            SurfaceFormat::R32Sfloat => 1,
+            SurfaceFormat::R32Uint => 1,
+            SurfaceFormat::R32Sint => 1,
+            SurfaceFormat::R16Unorm => 1,
+            SurfaceFormat::R16Snorm => 1,
+            SurfaceFormat::R16Sfloat => 1,
+            SurfaceFormat::R8G8Unorm => 2,
+            SurfaceFormat::R8G8Snorm => 2,
+            SurfaceFormat::R16G16Unorm => 2,
+            SurfaceFormat::R16G16Snorm => 2,
+            SurfaceFormat::R16G16Sfloat => 2,
+            SurfaceFormat::R8G8B8A8Unorm => 4,
+            SurfaceFormat::R8G8B8A8Srgb => 4,
+            SurfaceFormat::B8G8R8A8Unorm => 4,
+            SurfaceFormat::B8G8R8A8Srgb => 4,
+            SurfaceFormat::R16G16B16A16Unorm => 4,
+            SurfaceFormat::R16G16B16A16Snorm => 4,
+            SurfaceFormat::R16G16B16A16Sfloat => 4,
+            SurfaceFormat::R10G10B10A2Unorm => 4,
+            SurfaceFormat::BC1Unorm => 4,
+            SurfaceFormat::BC1Srgb => 4,
+            SurfaceFormat::BC2Unorm => 4,
+            SurfaceFormat::BC2Srgb => 4,
+            SurfaceFormat::BC3Unorm => 4,
+            SurfaceFormat::BC3Srgb => 4,
+            SurfaceFormat::BC4Unorm => 1,
+            SurfaceFormat::BC4Snorm => 1,
+            SurfaceFormat::BC5Unorm => 2,
+            SurfaceFormat::BC5Snorm => 2,
+            SurfaceFormat::BC6Sfloat => 3,
+            SurfaceFormat::BC6Ufloat => 3,
+            SurfaceFormat::BC7Unorm => 4,
+            SurfaceFormat::BC7Srgb => 4,
+            SurfaceFormat::Etc2Rgb8Unorm => 3,
+            SurfaceFormat::EacR11Unorm => 1,
+            SurfaceFormat::Astc4x4Unorm => 4,
+            SurfaceFormat::Astc4x4Srgb => 4,
+            SurfaceFormat::Astc6x6Unorm => 4,
+            SurfaceFormat::Astc6x6Srgb => 4,
+            SurfaceFormat::Astc8x8Unorm => 4,
+            SurfaceFormat::Astc8x8Srgb => 4,
+        }
+    }
+
+    /// Returns `true` if the format stores floating point values like BC6H.
+    pub fn is_floating_point(&self) -> bool {
+        matches!(
+            self,
+            SurfaceFormat::R32Sfloat
+                | SurfaceFormat::R16Sfloat
+                | SurfaceFormat::R16G16Sfloat
+                | SurfaceFormat::R16G16B16A16Sfloat
+                | SurfaceFormat::BC6Sfloat
+                | SurfaceFormat::BC6Ufloat
+        )
+    }
+
+    /// Returns `true` if the format stores signed values that can be negative
+    /// like signed normalized formats in the range `[-1, 1]`.
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            SurfaceFormat::R32Sfloat
+                | SurfaceFormat::R32Sint
+                | SurfaceFormat::R16Snorm
+                | SurfaceFormat::R16Sfloat
+                | SurfaceFormat::R8G8Snorm
+                | SurfaceFormat::R16G16Snorm
+                | SurfaceFormat::R16G16Sfloat
+                | SurfaceFormat::R16G16B16A16Snorm
+                | SurfaceFormat::R16G16B16A16Sfloat
+                | SurfaceFormat::BC4Snorm
+                | SurfaceFormat::BC5Snorm
+                | SurfaceFormat::BC6Sfloat
+        )
+    }
+
+    fn is_srgb(&self) -> bool {
+        matches!(
+            self,
+            SurfaceFormat::R8G8B8A8Srgb
+                | SurfaceFormat::B8G8R8A8Srgb
+                | SurfaceFormat::BC1Srgb
+                | SurfaceFormat::BC2Srgb
+                | SurfaceFormat::BC3Srgb
+                | SurfaceFormat::BC7Srgb
+                | SurfaceFormat::Astc4x4Srgb
+                | SurfaceFormat::Astc6x6Srgb
+                | SurfaceFormat::Astc8x8Srgb
+        )
+    }
+
+    fn supports_3d(&self) -> bool {
+        // 3D textures using R8 are not supported on all hardware configurations.
+        !matches!(self, SurfaceFormat::R8Unorm)
+    }
+}
+
+#[derive(BinRead, Debug, Clone)]
+#[br(magic = b"BRTI")]
+#[allow(dead_code)]
+struct Brti {
+    size: u32,  // offset?
+    size2: u64, // size?
+    flags: u8,
+    texture_dimension: TextureDimension,
+    tile_mode: u16,
+    swizzle: u16,
+    mipmap_count: u16,
+    multi_sample_count: u32,
+    #[br(try_map = |x: u32| SurfaceFormat::try_from(x))]
+    format: SurfaceFormat,
+    unk2: u32,
+    width: u32,
+    height: u32,
+    depth: u32,
+    layer_count: u32,
+    block_height_log2: u32,
+    unk4: [u32; 6],  // TODO: What is this?
+    image_size: u32, // the total size of all layers and mipmaps with padding excluding the BRTD header
+    align: u32,      // the size of a block of GOBs for the mip 0 block height.
+    comp_sel: u32,
+    texture_view_dimension: TextureViewDimension,
+
+    #[br(parse_with = FilePtr64::parse)]
+    name_addr: BntxStr,
+
+    parent_addr: u64, // pointer to nx header
+
+    // TODO: This is a pointer to an array of u64 mipmap offsets.
+    // TODO: Parse the entire surface in one vec but store the mipmap offsets?
+    #[br(parse_with = FilePtr64::parse, args { inner: (mipmap_count,)})]
+    mipmaps: Mipmaps, // u64 pointer to mipmap offsets?
+    unk5: u64, // padding?
+    unk6: u64, // offset?
+    unk7: u64, // offset?
+}
+
+#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq)]
+#[brw(repr(u8))]
+pub enum TextureDimension {
+    D1 = 1,
+    D2 = 2,
+    D3 = 3,
+}
+
+#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq)]
+#[brw(repr(u32))]
+pub enum TextureViewDimension {
+    D1 = 0,
+    D2 = 1,
+    D3 = 2,
+    Cube = 3,
+    D1Array = 4,
+    D2Array = 5,
+    D2Multisample = 6,
+    D2MultisampleArray = 7,
+    CubeArray = 8,
+}
+
+impl TextureViewDimension {
+    /// The view dimension for a texture with the given array layers and depth.
+    /// Textures with exactly 6 layers are assumed to be cube maps.
+    pub fn from_layers_depth(layer_count: u32, depth: u32) -> Self {
+        if depth > 1 {
+            Self::D3
+        } else if layer_count == 6 {
+            Self::Cube
+        } else if layer_count > 1 {
+            Self::D2Array
+        } else {
+            Self::D2
+        }
+    }
+}
+
+/// The dimensions and location of a single mipmap in the swizzled image data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MipmapInfo {
+    pub level: u32,
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The depth in pixels.
+    pub depth: u32,
+    /// The absolute offset in the file for the first array layer.
+    pub offset: u64,
+    /// The size in bytes of the swizzled data for a single array layer.
+    pub size: usize,
+}
+
+/// The arrangement of mipmaps and array layers in unswizzled image data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureLayout {
+    pub mip_order: MipOrder,
+    pub layer_order: LayerOrder,
+    /// The byte offset of each mipmap in the order described by `layer_order`.
+    /// For [LayerOrder::LayerMajor], the offset for layer `l` and mipmap `m`
+    /// is at index `l * mipmap_count + m`.
+    pub offsets: Vec<usize>,
+}
+
+/// The order of mipmaps within the image data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipOrder {
+    /// The base mipmap comes first followed by progressively smaller mipmaps.
+    LargestFirst,
+    /// The smallest mipmap comes first followed by progressively larger mipmaps.
+    SmallestFirst,
+}
+
+/// The grouping of array layers and mipmaps within the image data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerOrder {
+    /// All mipmaps for a layer are stored before the next layer.
+    LayerMajor,
+    /// A mipmap for all layers is stored before the next mipmap.
+    MipMajor,
+}
+
+/// Common channel layouts for the texture's component selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelHint {
+    /// Red, green, blue, and alpha channels in order.
+    Rgba,
+    /// Red and blue channels swapped.
+    Bgra,
+    /// The red channel in RGB and green channel in alpha like for normal maps.
+    Rrrg,
+    /// The red channel in RGB with opaque alpha like for grayscale images.
+    Rrr1,
+}
+
+impl ChannelHint {
+    fn comp_sel(&self) -> u32 {
+        // Each byte selects the source for the R, G, B, and A channels starting from the low byte.
+        // 0 = zero, 1 = one, 2 = red, 3 = green, 4 = blue, 5 = alpha
+        let [r, g, b, a] = match self {
+            ChannelHint::Rgba => [2, 3, 4, 5],
+            ChannelHint::Bgra => [4, 3, 2, 5],
+            ChannelHint::Rrrg => [2, 2, 2, 3],
+            ChannelHint::Rrr1 => [2, 2, 2, 1],
+        };
+        u32::from_le_bytes([r, g, b, a])
+    }
+}
+
+const SIZE_OF_BRTI: usize = 0xA0;
+
+// The zeroed region between the BRTI and the mipmap offsets array.
+// The last two BRTI pointers point to the two 0x100 byte halves of this region,
+// which are reserved for the GPU texture and texture view objects at runtime.
+const MIPMAP_OFFSET_ARRAY_PADDING: usize = 512;
+
+/// Converts an offset or size to the u32 used by the file without truncating.
+fn u32_offset(offset: usize) -> Result<u32, BntxError> {
+    u32::try_from(offset).map_err(|_| BntxError::OffsetOverflow { offset })
+}
+
+/// Checks that the end of the image data fits in the u32 offsets used by the file.
+fn check_data_offsets(texture_data_start: usize, data_len: usize) -> Result<(), BntxError> {
+    let offset = texture_data_start.saturating_add(data_len);
+    if offset > u32::MAX as usize {
+        Err(BntxError::OffsetOverflow { offset })
+    } else {
+        Ok(())
+    }
+}
+
+/// The size of all sections before the padding for the BRTD.
+fn size_before_brtd(
+    str_section_size: usize,
+    dict_section_size: usize,
+    mipmap_count: usize,
+) -> usize {
+    START_OF_STR_SECTION
+        + str_section_size
+        + dict_section_size
+        + SIZE_OF_BRTI
+        + MIPMAP_OFFSET_ARRAY_PADDING
+        + mipmap_count * size_of::<u64>()
+}
+
+impl Brti {
+    fn write_options<W: io::Write + io::Seek>(
+        &self,
+        writer: &mut W,
+        endian: binrw::Endian,
+        parent: &BntxFile,
+    ) -> Result<(), binrw::error::Error> {
+        (
+            (
+                b"BRTI",
+                self.size,
+                self.size2,
+                self.flags,
+                self.texture_dimension,
+                self.tile_mode,
+                self.swizzle,
+                self.mipmap_count,
+                self.multi_sample_count,
+                &self.format,
+                self.unk2,
+                self.width,
+                self.height,
+                self.depth,
+                self.layer_count,
+                self.block_height_log2,
+                self.unk4,
+                self.image_size,
+                self.align,
+                self.comp_sel,
+            ),
+            self.texture_view_dimension,
+            parent.name_offset_in_file() as u64,
+            self.parent_addr,
+            // The mipmap offsets pointer is filled in after writing.
+            0u64,
+            0u64,
+            (START_OF_STR_SECTION
+                + parent.header.inner.str_section.get_size()
+                + parent.nx_header.dict.get_size()
+                + SIZE_OF_BRTI) as u64,
+            (START_OF_STR_SECTION
+                + parent.header.inner.str_section.get_size()
+                + parent.nx_header.dict.get_size()
+                + SIZE_OF_BRTI
+                + MIPMAP_OFFSET_ARRAY_PADDING / 2) as u64,
+            0u64,
+            0u64,
+        )
+            .write_options(writer, endian, ())
+    }
+}
+
+#[binrw]
+#[brw(magic = b"BRTD")]
+struct Brtd {
+    // Size of the image data + BRTD header.
+    #[brw(pad_before = 4)]
+    #[br(temp)]
+    #[bw(calc = (image_data.len() + BRTD_HEADER_SIZE) as u64)]
+    brtd_size: u64,
+
+    #[br(count = brtd_size - BRTD_HEADER_SIZE as u64)]
+    image_data: Vec<u8>,
+}
+
+impl fmt::Debug for Brtd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ImageData[{:?}]", self.image_data.len())
+    }
+}
+
+use binrw::io::{Read, Seek};
+
+fn read_double_indirect<'a, T: BinRead, R: Read + Seek>(
+    reader: &mut R,
+    endian: binrw::Endian,
+    args: T::Args<'a>,
+) -> BinResult<T> {
+    let offset1 = <u64>::read_options(reader, endian, ())?;
+    let position = reader.stream_position()?;
+
+    reader.seek(SeekFrom::Start(offset1))?;
+    let offset2 = <u64>::read_options(reader, endian, ())?;
+
+    reader.seek(SeekFrom::Start(offset2))?;
+    let value = T::read_options(reader, endian, args)?;
+
+    reader.seek(SeekFrom::Start(position))?;
+    Ok(value)
+}
+
+#[derive(BinRead, Debug, Clone)]
+#[br(import(mipmap_count: u16))]
+struct Mipmaps {
+    #[br(count = u32::from(mipmap_count))]
+    mipmap_offsets: Vec<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dds::create_bntx;
+    use crate::dds::create_dds;
+    use std::io::BufWriter;
+
+    #[test]
+    fn try_parse() {
+        let original = BntxFile::from_file("chara_1_mario_00.bntx").unwrap();
+        original.write_to_file("chara_1_mario_00.out.bntx").unwrap();
+
+        let dds = create_dds(&original).unwrap();
+        let mut writer = BufWriter::new(std::fs::File::create("chara_1_mario_00.dds").unwrap());
+        dds.write(&mut writer).unwrap();
+
+        create_bntx("chara_1_mario_00", &dds)
+            .unwrap()
+            .write_to_file("chara_1_mario_00.dds.bntx")
+            .unwrap();
+    }
+
+    #[test]
+    fn from_image_data_3d_r8() {
+        let result = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            4,
+            1,
+            1,
+            SurfaceFormat::R8Unorm,
+            &[0u8; 4 * 4 * 4],
+        );
+        assert!(matches!(
+            result,
+            Err(BntxError::IncompatibleDimensionForFormat {
+                format: SurfaceFormat::R8Unorm,
+                depth: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn from_image_data_too_many_mipmaps() {
+        let result = BntxFile::from_image_data(
+            "tex",
+            64,
+            64,
+            1,
+            14,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 64 * 64 * 4 * 2],
+        );
+        assert!(matches!(
+            result,
+            Err(BntxError::TooManyMipmaps {
+                requested: 14,
+                max: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn max_mipmap_counts() {
+        assert_eq!(1, max_mipmap_count(1, 1, 1));
+        assert_eq!(7, max_mipmap_count(64, 64, 1));
+        assert_eq!(10, max_mipmap_count(512, 300, 1));
+        assert_eq!(4, max_mipmap_count(4, 4, 8));
+    }
+
+    #[test]
+    fn read_mipmap_from_reader_layers() {
+        // 8x8 and 4x4 mipmaps for 2 layers of R8G8B8A8.
+        let data: Vec<u8> = (0..(8 * 8 + 4 * 4) * 4 * 2).map(|i| i as u8).collect();
+        let bntx =
+            BntxFile::from_image_data("tex", 8, 8, 1, 2, 2, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+
+        let layer_size = (8 * 8 + 4 * 4) * 4;
+        let mip1 = bntx.read_mipmap_from_reader(&mut writer, 1, 1).unwrap();
+        assert_eq!(&data[layer_size + 8 * 8 * 4..layer_size * 2], &mip1[..]);
+
+        assert!(matches!(
+            bntx.read_mipmap_from_reader(&mut writer, 2, 0),
+            Err(BntxError::InvalidSubresource { layer: 2, mip: 0 })
+        ));
+    }
+
+    #[test]
+    fn brti_written_size() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.nx_header
+            .brti
+            .write_options(&mut writer, binrw::Endian::Little, &bntx)
+            .unwrap();
+        assert_eq!(SIZE_OF_BRTI, writer.into_inner().len());
+    }
+
+    #[test]
+    fn from_image_data_checked_invalid_length() {
+        assert!(matches!(
+            BntxFile::from_image_data_checked(
+                "tex",
+                4,
+                4,
+                1,
+                1,
+                1,
+                SurfaceFormat::BC7Srgb,
+                &[0u8; 15]
+            ),
+            Err(BntxError::UnalignedDataLength {
+                format: SurfaceFormat::BC7Srgb,
+                len: 15,
+                block_size: 16
+            })
+        ));
+
+        assert!(matches!(
+            BntxFile::from_image_data_checked(
+                "tex",
+                8,
+                8,
+                1,
+                1,
+                1,
+                SurfaceFormat::BC7Srgb,
+                &[0u8; 32]
+            ),
+            Err(BntxError::NotEnoughData {
+                expected: 64,
+                actual: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn crop_rgba() {
+        let data: Vec<u8> = (0..4 * 4 * 4).map(|i| i as u8).collect();
+        let bntx =
+            BntxFile::from_image_data("tex", 4, 4, 1, 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+
+        let cropped = bntx.crop(1, 2, 2, 2).unwrap();
+        assert_eq!((2, 2), (cropped.width(), cropped.height()));
+        assert_eq!(
+            [&data[36..44], &data[52..60]].concat(),
+            cropped.deswizzled_data().unwrap()
+        );
+
+        assert!(matches!(
+            bntx.crop(3, 0, 2, 1),
+            Err(BntxError::InvalidRegion { .. })
+        ));
+    }
+
+    #[test]
+    fn crop_preserves_settings() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .parent_offset(0x40)
+            .format_version(1, 2)
+            .build()
+            .unwrap();
+
+        let cropped = bntx.crop(0, 0, 2, 2).unwrap();
+        assert_eq!(0x40, cropped.nx_header.brti.parent_addr);
+        assert_eq!((1, 2), cropped.header.version);
+    }
+
+    #[test]
+    fn zero_mipmaps() {
+        let bntx =
+            BntxFile::from_image_data("tex", 4, 4, 1, 0, 1, SurfaceFormat::R8G8B8A8Unorm, &[])
+                .unwrap();
+        assert_eq!(0, bntx.num_mipmaps());
+        assert!(bntx.deswizzled_data().unwrap().is_empty());
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        writer.set_position(0);
+        let bntx: BntxFile = writer.read_le().unwrap();
+        assert_eq!(0, bntx.num_mipmaps());
+        assert!(bntx.deswizzled_data().unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_image_data_mipmaps_layers() {
+        let mip0: Vec<u8> = (0..4 * 4 * 2).collect();
+        let mip1: Vec<u8> = (100..100 + 2 * 2 * 2).collect();
+        let bntx = BntxFile::from_image_data_mipmaps(
+            "tex",
+            4,
+            4,
+            1,
+            2,
+            SurfaceFormat::R8Unorm,
+            &[&mip0, &mip1],
+        )
+        .unwrap();
+        assert_eq!(2, bntx.num_mipmaps());
+        assert_eq!(
+            [&mip0[..16], &mip1[..4], &mip0[16..], &mip1[4..]].concat(),
+            bntx.deswizzled_data().unwrap()
+        );
+
+        assert!(matches!(
+            BntxFile::from_image_data_mipmaps(
+                "tex",
+                4,
+                4,
+                1,
+                2,
+                SurfaceFormat::R8Unorm,
+                &[&mip0, &mip1[..4]],
+            ),
+            Err(BntxError::InvalidMipmapSize {
+                mip: 1,
+                expected: 8,
+                actual: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn replace_swizzled_data_from_dds() {
+        let mut bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+        assert_eq!(vec![0u8; 4 * 4 * 4], bntx.deswizzled_data().unwrap());
+
+        let mut dds = create_dds(&bntx).unwrap();
+        dds.data = (0..4 * 4 * 4).collect();
+        bntx.replace_swizzled_data_from_dds(&dds).unwrap();
+        assert_eq!(dds.data, bntx.deswizzled_data().unwrap());
+
+        let other = BntxFile::from_image_data(
+            "tex",
+            8,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 8 * 4 * 4],
+        )
+        .unwrap();
+        assert!(matches!(
+            bntx.replace_swizzled_data_from_dds(&create_dds(&other).unwrap()),
+            Err(BntxError::DimensionMismatch {
+                field: "width",
+                expected: 4,
+                actual: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn from_image_data_default_name() {
+        let bntx = BntxFile::from_image_data(
+            None,
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+        assert_eq!("texture", bntx.header.inner.file_name);
+    }
+
+    #[test]
+    fn set_mipmap_count() {
+        let data: Vec<u8> = (0..8 * 8 * 4).map(|i| i as u8).collect();
+        let mut bntx =
+            BntxFile::from_image_data("tex", 8, 8, 1, 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+
+        bntx.set_mipmap_count(3).unwrap();
+        assert_eq!(3, bntx.num_mipmaps());
+        let mut expected = data.clone();
+        expected.resize((8 * 8 + 4 * 4 + 2 * 2) * 4, 0u8);
+        assert_eq!(expected, bntx.deswizzled_data().unwrap());
+
+        bntx.set_mipmap_count(1).unwrap();
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+
+        assert!(matches!(
+            bntx.set_mipmap_count(0),
+            Err(BntxError::InvalidMipmapCount(0))
+        ));
+    }
+
+    #[test]
+    fn set_mipmap_count_header_too_large() {
+        let data: Vec<u8> = (0..8 * 8 * 4).map(|i| i as u8).collect();
+        let builder = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::R8G8B8A8Unorm, &data);
+        let bntx = builder.build().unwrap();
+
+        // Leave space for exactly one mipmap offset before the BRTD.
+        let brtd_offset = size_before_brtd(
+            bntx.header.inner.str_section.get_size(),
+            bntx.nx_header.dict.get_size(),
+            1,
+        );
+        let mut bntx = builder.brtd_offset(brtd_offset).build().unwrap();
+        let header = format!("{bntx:?}");
+
+        assert!(matches!(
+            bntx.set_mipmap_count(2),
+            Err(BntxError::HeaderTooLarge { max, .. }) if max == brtd_offset
+        ));
+        assert_eq!(header, format!("{bntx:?}"));
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+    }
+
+    /// A path in the temp directory that is unique to `test_name` and the current process.
+    fn temp_path(test_name: &str, extension: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "bntx_{test_name}_{}.{extension}",
+            std::process::id()
+        ))
+    }
+
+    /// The temporary files created by [BntxFile::write_to_file_atomic] for `path`.
+    fn temp_files(path: &Path) -> Vec<std::path::PathBuf> {
+        let prefix = format!(".{}.", path.file_name().unwrap().to_str().unwrap());
+        std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&prefix))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn from_image_file_linear_png() {
+        let path = temp_path("from_image_file_linear_png", "png");
+        let mut encoder = png::Encoder::new(std::fs::File::create(&path).unwrap(), 1, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_source_gamma(png::ScaledFloat::new(1.0));
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1, 2, 3, 4]).unwrap();
+        writer.finish().unwrap();
+
+        let bntx = BntxFile::from_image_file(&path, "tex").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(SurfaceFormat::R8G8B8A8Unorm, bntx.image_format());
+        assert_eq!(vec![1, 2, 3, 4], bntx.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn read_le_incorrect_bom() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let mut bytes = writer.into_inner();
+        bytes[12..14].copy_from_slice(b"\xFE\xFF");
+
+        assert!(std::io::Cursor::new(&bytes).read_le::<BntxFile>().is_err());
+
+        let bntx = BntxFile::read_le(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(4, bntx.width());
+        assert_eq!(1, bntx.texture_count());
+        assert_eq!("tex", bntx.header.inner.file_name);
+    }
+
+    #[test]
+    fn dump_header_hex() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let dump = bntx.dump_header_hex().unwrap();
+        assert_eq!(0x1000 / 16, dump.lines().count());
+        assert!(dump.starts_with(
+            "00000000  42 4e 54 58 00 00 00 00  00 00 04 00 ff fe 0c 40  |BNTX...........@|\n"
+        ));
+    }
+
+    #[test]
+    fn dump_header_hex_invalid_layout() {
+        let mut bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            3,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4 + 2 * 2 * 4 + 4],
+        )
+        .unwrap();
+        bntx.nx_header.brti.mipmaps.mipmap_offsets.swap(1, 2);
+
+        assert!(matches!(
+            bntx.dump_header_hex(),
+            Err(BntxError::UnorderedMipmapOffsets { .. })
+        ));
+    }
+
+    #[test]
+    fn from_image_data_view_dimension() {
+        let dimensions = |layers, depth| {
+            let data = vec![0u8; 4 * 4 * 4 * (layers * depth) as usize];
+            let brti = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .layer_count(layers)
+                .depth(depth)
+                .build()
+                .unwrap()
+                .nx_header
+                .brti;
+            (brti.texture_dimension, brti.texture_view_dimension)
+        };
+        assert_eq!(
+            (TextureDimension::D2, TextureViewDimension::D2),
+            dimensions(1, 1)
+        );
+        assert_eq!(
+            (TextureDimension::D2, TextureViewDimension::D2Array),
+            dimensions(2, 1)
+        );
+        assert_eq!(
+            (TextureDimension::D2, TextureViewDimension::Cube),
+            dimensions(6, 1)
+        );
+        assert_eq!(
+            (TextureDimension::D3, TextureViewDimension::D3),
+            dimensions(1, 4)
+        );
+    }
+
+    #[test]
+    fn set_dimensions() {
+        let data: Vec<u8> = (0..8 * 8 * 4).map(|i| i as u8).collect();
+        let mut bntx =
+            BntxFile::from_image_data("tex", 8, 8, 1, 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+
+        bntx.set_dimensions(4, 16, 1).unwrap();
+        assert_eq!((4, 16, 1), (bntx.width(), bntx.height(), bntx.depth()));
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+
+        assert!(matches!(
+            bntx.set_dimensions(8, 16, 1),
+            Err(BntxError::DataSizeMismatch {
+                expected: 512,
+                actual: 256
+            })
+        ));
+        assert_eq!((4, 16), (bntx.width(), bntx.height()));
+    }
+
+    #[test]
+    fn set_dimensions_preserves_settings() {
+        let data: Vec<u8> = (0..8 * 8 * 4).map(|i| i as u8).collect();
+        let mut bntx = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .brtd_offset(0x1FF0)
+            .flags(0)
+            .build()
+            .unwrap();
+
+        bntx.set_dimensions(16, 4, 1).unwrap();
+        assert_eq!(0x1FF0, bntx.brtd_start());
+        assert_eq!(0, bntx.nx_header.brti.flags);
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+
+        let bntx = BntxFile::from_bytes(&bntx.to_bytes().unwrap()).unwrap();
+        assert_eq!((16, 4), (bntx.width(), bntx.height()));
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn from_image_color_types() {
+        let luma = image::GrayImage::from_raw(1, 1, vec![7]).unwrap();
+        let bntx = BntxFile::from_image(luma.into(), "tex").unwrap();
+        assert_eq!(SurfaceFormat::R8Unorm, bntx.image_format());
+        assert_eq!(vec![7], bntx.deswizzled_data().unwrap());
+
+        let rgb = image::RgbImage::from_raw(1, 1, vec![1, 2, 3]).unwrap();
+        let bntx = BntxFile::from_image(rgb.into(), "tex").unwrap();
+        assert_eq!(SurfaceFormat::R8G8B8A8Srgb, bntx.image_format());
+        assert_eq!(vec![1, 2, 3, 255], bntx.deswizzled_data().unwrap());
+
+        let rgba16 =
+            image::ImageBuffer::<image::Rgba<u16>, _>::from_raw(1, 1, vec![0, 0xFFFF, 0, 0xFFFF])
+                .unwrap();
+        let bntx = BntxFile::from_image(rgba16.into(), "tex").unwrap();
+        assert_eq!(SurfaceFormat::R16G16B16A16Sfloat, bntx.image_format());
+        assert_eq!(
+            vec![0x00, 0x00, 0x00, 0x3C, 0x00, 0x00, 0x00, 0x3C],
+            bntx.deswizzled_data().unwrap()
+        );
+
+        let rgb32f = image::Rgb32FImage::from_raw(1, 1, vec![2.0, 0.5, -1.0]).unwrap();
+        let bntx = BntxFile::from_image(rgb32f.into(), "tex").unwrap();
+        assert_eq!(SurfaceFormat::R16G16B16A16Sfloat, bntx.image_format());
+        assert_eq!(
+            vec![0x00, 0x40, 0x00, 0x38, 0x00, 0xBC, 0x00, 0x3C],
+            bntx.deswizzled_data().unwrap()
+        );
+    }
+
+    #[test]
+    fn builder_parent_offset() {
+        let data = [0u8; 4 * 4 * 4];
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .parent_offset(0x40)
+            .build()
+            .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bntx = BntxFile::read_le(&mut std::io::Cursor::new(writer.into_inner())).unwrap();
+        assert_eq!(0x40, bntx.nx_header.brti.parent_addr);
+    }
+
+    #[test]
+    fn verify_magic_bytes() {
+        let mut reader = std::io::Cursor::new(b"BNTX\0\0\0\0");
+        assert!(BntxFile::verify_magic_bytes(&mut reader).unwrap());
+        assert_eq!(0, reader.position());
+
+        assert!(!BntxFile::verify_magic_bytes(&mut std::io::Cursor::new(b"DDS ")).unwrap());
+        assert!(!BntxFile::verify_magic_bytes(&mut std::io::Cursor::new(b"BN")).unwrap());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn from_array_view_rgb() {
+        let array = ndarray::Array3::from_shape_vec((1, 2, 3), vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let bntx = BntxFile::from_array_view("tex", array.view()).unwrap();
+        assert_eq!((2, 1), (bntx.width(), bntx.height()));
+        assert_eq!(SurfaceFormat::R8G8B8A8Srgb, bntx.image_format());
+        assert_eq!(
+            vec![1, 2, 3, 255, 4, 5, 6, 255],
+            bntx.deswizzled_data().unwrap()
+        );
+
+        let array = ndarray::Array3::<u8>::zeros((1, 1, 5));
+        assert!(matches!(
+            BntxFile::from_array_view("tex", array.view()),
+            Err(BntxError::UnsupportedChannelCount(5))
+        ));
+    }
+
+    #[test]
+    fn deswizzle_to_vec_with_layout() {
+        let data = vec![0u8; (4 * 4 + 2 * 2 + 1) * 2];
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8Unorm, &data)
+            .mipmap_count(3)
+            .layer_count(2)
+            .build()
+            .unwrap();
+
+        let (deswizzled, layout) = bntx.deswizzle_to_vec_with_layout().unwrap();
+        assert_eq!(data.len(), deswizzled.len());
+        assert_eq!(MipOrder::LargestFirst, layout.mip_order);
+        assert_eq!(LayerOrder::LayerMajor, layout.layer_order);
+        assert_eq!(vec![0, 16, 20, 21, 37, 41], layout.offsets);
+    }
+
+    #[test]
+    fn write_brtd_only() {
+        let data: Vec<u8> = (0..4 * 4 * 4).map(|i| i as u8).collect();
+        let bntx =
+            BntxFile::from_image_data("tex", 4, 4, 1, 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let file = writer.into_inner();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write_brtd_only(&mut writer).unwrap();
+        let brtd = writer.into_inner();
+
+        assert_eq!(
+            &file[BRTD_SECTION_START..BRTD_SECTION_START + brtd.len()],
+            &brtd[..]
+        );
+        assert_eq!(b"BRTD", &brtd[..4]);
+    }
+
+    #[test]
+    fn builder_strict_zero_blocks() {
+        let mut data = vec![0u8; 8 * 8 / 16 * 8];
+        data[..8].fill(1);
+
+        let builder = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::BC1Unorm, &data);
+        assert!(builder.build().is_ok());
+        assert!(matches!(
+            builder.strict(true).build(),
+            Err(BntxError::SuspectPixelData {
+                zero_blocks: 3,
+                total_blocks: 4
+            })
+        ));
+
+        data[8..16].fill(1);
+        let builder = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::BC1Unorm, &data);
+        assert!(builder.strict(true).build().is_ok());
+    }
+
+    #[test]
+    fn write_fills_offsets() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+
+        let read_u32 =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let reloc_table_start = read_u32(0x18) as usize;
+        assert_eq!(b"_RLT", &bytes[reloc_table_start..reloc_table_start + 4]);
+        assert_eq!(bytes.len(), read_u32(0x1C) as usize);
+        assert_eq!(bytes.len(), bntx.size_on_disk().unwrap());
+        assert_eq!(BRTD_SECTION_START, read_u32(0x30) as usize);
+        assert_eq!(
+            bntx.nx_header.brti.image_size as usize + BRTD_HEADER_SIZE,
+            read_u32(BRTD_SECTION_START + 8) as usize
+        );
+    }
+
+    #[test]
+    fn write_relocation_table() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            3,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4 + 2 * 2 * 4 + 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        writer.set_position(0);
+        let new = BntxFile::read_le(&mut writer).unwrap();
+        let table = &new.header.inner.reloc_table;
+
+        let mut pointers = Vec::new();
+        for section in &table.sections {
+            let entries = &table.entries[section.index as usize..][..section.count as usize];
+            let mut section_pointers = Vec::new();
+            for entry in entries {
+                for i in 0..entry.struct_count as u32 {
+                    let start = entry.position
+                        + i * (entry.offset_count as u32 + entry.padding_count as u32) * 8;
+                    section_pointers.extend((0..entry.offset_count as u32).map(|j| start + j * 8));
+                }
+            }
+            section_pointers.sort_unstable();
+            pointers.push(section_pointers);
+        }
+
+        let str_dict_size = START_OF_STR_SECTION
+            + new.header.inner.str_section.get_size()
+            + new.nx_header.dict.get_size();
+        let brti = str_dict_size as u32;
+        let dict = (START_OF_STR_SECTION + new.header.inner.str_section.get_size()) as u32;
+        assert_eq!(
+            vec![
+                0x28,
+                0x38,
+                0x40,
+                0x198,
+                dict + 0x10,
+                dict + 0x20,
+                brti + 0x60,
+                brti + 0x68,
+                brti + 0x70,
+                brti + 0x80,
+                brti + 0x88
+            ],
+            pointers[0]
+        );
+
+        // Pointers to the image data are relocated relative to the BRTD section.
+        assert_eq!(BRTD_SECTION_START as u32, table.sections[1].position);
+        let mipmaps = brti + (SIZE_OF_BRTI + MIPMAP_OFFSET_ARRAY_PADDING) as u32;
+        assert_eq!(vec![0x30, mipmaps, mipmaps + 8, mipmaps + 16], pointers[1]);
+    }
+
+    #[test]
+    fn write_zeroed_memory_pool() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0xFFu8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+
+        assert!(bytes[HEADER_SIZE..HEADER_SIZE + MEM_POOL_SIZE]
+            .iter()
+            .all(|b| *b == 0));
+    }
+
+    #[test]
+    fn builder_swizzled_data_layout() {
+        let data: Vec<u8> = (0..16 * 16 * 4).map(|i| i as u8).collect();
+        let linear = BntxFileBuilder::new("tex", 16, 16, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .build()
+            .unwrap();
+
+        let swizzled = &linear.nx_header.brtd.image_data;
+        let bntx = BntxFileBuilder::new("tex", 16, 16, SurfaceFormat::R8G8B8A8Unorm, swizzled)
+            .data_layout(DataLayout::Swizzled)
+            .build()
+            .unwrap();
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+
+        assert!(matches!(
+            BntxFileBuilder::new("tex", 16, 16, SurfaceFormat::R8G8B8A8Unorm, &swizzled[1..])
+                .data_layout(DataLayout::Swizzled)
+                .build(),
+            Err(BntxError::DataSizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn builder_linear_color_space() {
+        let data = [0u8, 128, 255, 128];
+        let bntx = BntxFileBuilder::new("tex", 1, 1, SurfaceFormat::R8G8B8A8Srgb, &data)
+            .color_space(ColorSpace::Linear)
+            .build()
+            .unwrap();
+        assert_eq!(vec![0, 188, 255, 128], bntx.deswizzled_data().unwrap());
+
+        let bntx = BntxFileBuilder::new("tex", 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .color_space(ColorSpace::Linear)
+            .build()
+            .unwrap();
+        assert_eq!(data.to_vec(), bntx.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn builder_trim_trailing_zeros() {
+        let mut data = vec![0u8; 16 * 16 * 4];
+        data[0] = 1;
+        let bntx = BntxFileBuilder::new("tex", 16, 16, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .trim_trailing_zeros(true)
+            .build()
+            .unwrap();
+        assert_eq!(1, bntx.nx_header.brtd.image_data.len());
+        assert_eq!(
+            bntx.compute_surface_size_unswizzled(),
+            bntx.nx_header.brti.image_size as usize
+        );
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn builder_str_and_dict_sizes() {
+        let data = [0u8; 4 * 4 * 4];
+        let bntx =
+            BntxFileBuilder::new("chara_0_texture", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .build()
+                .unwrap();
+        assert_eq!(MEM_POOL_ADDRESS, bntx.nx_header.dict_size);
+        assert_eq!(0x58, bntx.header.inner.str_section.block_size);
+        assert_eq!(0x58, bntx.header.inner.str_section.block_offset);
+
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .build()
+            .unwrap();
+        assert_eq!(MEM_POOL_ADDRESS, bntx.nx_header.dict_size);
+        assert_eq!(0x48, bntx.header.inner.str_section.block_size);
+    }
+
+    #[test]
+    fn iter_array_layers() {
+        let data: Vec<u8> = (0..(8 * 8 + 4 * 4) * 3).map(|i| i as u8).collect();
+        let bntx = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::R8Unorm, &data)
+            .mipmap_count(2)
+            .layer_count(3)
+            .build()
+            .unwrap();
+
+        let layers: Vec<_> = bntx.iter_array_layers().map(Result::unwrap).collect();
+        assert_eq!(3, layers.len());
+        assert_eq!(data, layers.concat());
+    }
+
+    #[test]
+    fn write_dict_section() {
+        let data = [0u8; 4 * 4 * 4];
+        let bntx = BntxFileBuilder::new("tex2", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .build()
+            .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+
+        let start = START_OF_STR_SECTION + bntx.header.inner.str_section.get_size();
+        assert_eq!(
+            b"_DIC\x01\x00\x00\x00\xFF\xFF\xFF\xFF\x01\x00\x00\x00\xB4\x01\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x01\x00\xB8\x01\x00\x00\x00\x00\x00\x00",
+            &bytes[start..start + bntx.nx_header.dict.get_size()]
+        );
+    }
+
+    #[test]
+    fn dict_builder_lookup() {
+        let names = ["a", "b", "tex", "texture", "texture_nor", "c0", "c1", "c2"];
+        let mut builder = dict::DictBuilder::new();
+        for name in names {
+            builder.insert(name);
+        }
+        builder.insert("tex");
+        let dict = builder.build();
+        assert_eq!(names.len() as u32, dict.node_count);
+
+        // Follow the bits of each name until reaching a node with a smaller bit index.
+        let bit = |name: &str, bit: i32| {
+            let bytes = name.as_bytes();
+            let i = bit as usize / 8;
+            i < bytes.len() && (bytes[bytes.len() - 1 - i] >> (bit % 8)) & 1 != 0
+        };
+        for name in names {
+            let mut parent = 0;
+            let mut child = dict.nodes[0].left_index as usize;
+            while dict.nodes[parent].reference < dict.nodes[child].reference {
+                parent = child;
+                let node = &dict.nodes[child];
+                child = if bit(name, node.reference) {
+                    node.right_index as usize
+                } else {
+                    node.left_index as usize
+                };
+            }
+            assert_eq!(name, dict.nodes[child].name.chars);
+        }
+    }
+
+    #[test]
+    fn write_to_file_atomic() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[1u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let path = temp_path("write_to_file_atomic", "bntx");
+        bntx.write_to_file_atomic(&path).unwrap();
+        assert!(temp_files(&path).is_empty());
+
+        let bntx = BntxFile::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(vec![1u8; 4 * 4 * 4], bntx.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn validate_name_consistency() {
+        let mut bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+        assert!(bntx.validate_name_consistency().is_ok());
+
+        bntx.nx_header.brti.name_addr = BntxStr::from("other".to_owned());
+        assert!(matches!(
+            bntx.validate_name_consistency(),
+            Err(BntxError::InconsistentName {
+                location: "BRTI",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn describe() {
+        let data = vec![0u8; (64 * 64 + 32 * 32) / 16 * 16 * 2];
+        let bntx = BntxFileBuilder::new("mario_hat", 64, 64, SurfaceFormat::BC7Srgb, &data)
+            .mipmap_count(2)
+            .layer_count(2)
+            .build()
+            .unwrap();
+        assert_eq!(
+            "mario_hat: 64×64 BC7Srgb, 2 mips, 2 layers (10.0 KB)",
+            bntx.describe()
+        );
+    }
+
+    #[test]
+    fn from_image_data_swizzle_error_context() {
+        let result = BntxFile::from_image_data(
+            "tex",
+            8,
+            8,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4],
+        );
+        assert!(matches!(
+            result,
+            Err(BntxError::SwizzleSurface {
+                width: 8,
+                height: 8,
+                depth: 1,
+                format: SurfaceFormat::R8G8B8A8Unorm,
+                mipmap_count: 1,
+                layer_count: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn copy_header_from() {
+        let data: Vec<u8> = (0..8 * 8 * 4).map(|i| i as u8).collect();
+        let mut bntx =
+            BntxFile::from_image_data("a", 8, 8, 1, 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+        let template = BntxFile::from_image_data(
+            "template",
+            16,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Srgb,
+            &[0u8; 16 * 4 * 4],
+        )
+        .unwrap();
+
+        bntx.copy_header_from(&template).unwrap();
+        assert_eq!("template", bntx.header.inner.file_name);
+        assert_eq!((16, 4), (bntx.width(), bntx.height()));
+        assert_eq!(SurfaceFormat::R8G8B8A8Srgb, bntx.image_format());
+        assert!(bntx.validate_name_consistency().is_ok());
+
+        let other = BntxFile::from_image_data(
+            "other",
+            64,
+            64,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Srgb,
+            &[0u8; 64 * 64 * 4],
+        )
+        .unwrap();
+        assert!(matches!(
+            bntx.copy_header_from(&other),
+            Err(BntxError::DataSizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn surface_format_is_floating_point() {
+        assert!(SurfaceFormat::BC6Sfloat.is_floating_point());
+        assert!(SurfaceFormat::BC6Ufloat.is_floating_point());
+        assert!(!SurfaceFormat::BC7Unorm.is_floating_point());
+        assert!(!SurfaceFormat::R16G16B16A16Unorm.is_floating_point());
+    }
+
+    #[test]
+    fn surface_format_is_signed() {
+        assert!(SurfaceFormat::BC4Snorm.is_signed());
+        assert!(SurfaceFormat::BC5Snorm.is_signed());
+        assert!(SurfaceFormat::BC6Sfloat.is_signed());
+        assert!(!SurfaceFormat::BC5Unorm.is_signed());
+        assert!(!SurfaceFormat::BC6Ufloat.is_signed());
+    }
+
+    #[test]
+    fn surface_format_channel_count() {
+        assert_eq!(1, SurfaceFormat::R8Unorm.channel_count());
+        assert_eq!(2, SurfaceFormat::BC5Snorm.channel_count());
+        assert_eq!(3, SurfaceFormat::BC6Ufloat.channel_count());
+        assert_eq!(4, SurfaceFormat::B8G8R8A8Srgb.channel_count());
+    }
+
+    #[test]
+    fn from_image_data_header_too_large() {
+        let name = "a".repeat(BRTD_SECTION_START);
+        let result = BntxFile::from_image_data(
+            name.as_str(),
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        );
+        assert!(matches!(result, Err(BntxError::HeaderTooLarge { .. })));
+    }
+
+    #[test]
+    fn name_offset_in_file() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+
+        // The name is the first string after the empty string.
+        let offset = bntx.name_offset_in_file();
+        assert_eq!(
+            START_OF_STR_SECTION + STR_HEADER_SIZE + EMPTY_STR_SIZE,
+            offset
+        );
+        assert_eq!(&[3, 0], &bytes[offset..offset + 2]);
+        assert_eq!(b"tex", &bytes[offset + 2..offset + 5]);
+    }
+
+    #[test]
+    fn from_image_data_non_ascii_name() {
+        let result = BntxFile::from_image_data(
+            "テクスチャ",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        );
+        assert!(matches!(result, Err(BntxError::NonAsciiName(name)) if name == "テクスチャ"));
+    }
+
+    #[test]
+    fn check_data_offsets_overflow() {
+        let start = BRTD_SECTION_START + BRTD_HEADER_SIZE;
+        assert!(check_data_offsets(start, 1024).is_ok());
+        assert!(check_data_offsets(start, u32::MAX as usize - start).is_ok());
+        assert!(matches!(
+            check_data_offsets(start, u32::MAX as usize),
+            Err(BntxError::OffsetOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn from_image_data_brtd_offset_overflow() {
+        let result =
+            BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 4 * 4 * 4])
+                .brtd_offset(usize::MAX)
+                .build();
+        assert!(matches!(
+            result,
+            Err(BntxError::OffsetOverflow { offset }) if offset == usize::MAX
+        ));
+
+        assert_eq!(0x1000, u32_offset(0x1000).unwrap());
+        assert!(matches!(
+            u32_offset(u32::MAX as usize + 1),
+            Err(BntxError::OffsetOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn builder_format_version() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .build()
+            .unwrap();
+        assert_eq!((0, 4), bntx.header.version);
+
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .format_version(0, 3)
+            .build()
+            .unwrap();
+        assert_eq!((0, 3), bntx.header.version);
+    }
+
+    #[test]
+    fn format_tables() {
+        for format in formats::COMPRESSED_FORMATS {
+            assert!(format.block_dim() != BlockDim::uncompressed());
+            assert!(!formats::UNCOMPRESSED_FORMATS.contains(format));
+        }
+        for format in formats::UNCOMPRESSED_FORMATS {
+            assert!(format.block_dim() == BlockDim::uncompressed());
+        }
+        for format in formats::COMPRESSED_FORMATS
+            .iter()
+            .chain(formats::UNCOMPRESSED_FORMATS)
+        {
+            assert_eq!(format.is_srgb(), formats::SRGB_FORMATS.contains(format));
+            assert_eq!(
+                format.is_floating_point(),
+                formats::FLOAT_FORMATS.contains(format)
+            );
+        }
+    }
+
+    #[test]
+    fn builder_layer_count_depth() {
+        let data = [0u8; 4 * 4 * 4 * 4];
+        let result = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .depth(4)
+            .layer_count(4)
+            .build();
+        assert!(matches!(
+            result,
+            Err(BntxError::AmbiguousLayerDepth {
+                layer_count: 4,
+                depth: 4
+            })
+        ));
+
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .depth(4)
+            .layer_count(0)
+            .build()
+            .unwrap();
+        assert_eq!(1, bntx.nx_header.brti.layer_count);
+        assert_eq!(4, bntx.nx_header.brti.depth);
+    }
+
+    #[test]
+    fn write_unordered_mipmap_offsets() {
+        let mut bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            3,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4 + 2 * 2 * 4 + 4],
+        )
+        .unwrap();
+        bntx.nx_header.brti.mipmaps.mipmap_offsets.swap(1, 2);
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        assert!(matches!(
+            bntx.write(&mut writer),
+            Err(BntxError::UnorderedMipmapOffsets { mip: 2, .. })
+        ));
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn compute_relocation_table() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        writer.set_position(0);
+        let new = BntxFile::read_le(&mut writer).unwrap();
+
+        // Compare the debug output since the table does not implement PartialEq.
+        assert_eq!(
+            format!("{:?}", new.header.inner.reloc_table),
+            format!("{:?}", bntx.compute_relocation_table().unwrap())
+        );
+        assert_eq!(
+            format!("{:?}", new.header.inner.reloc_table),
+            format!("{:?}", bntx.header.inner.reloc_table)
+        );
+    }
+
+    #[test]
+    fn builder_multi_sample_count() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .multi_sample_count(4)
+            .build()
+            .unwrap();
+        assert_eq!(4, bntx.nx_header.brti.multi_sample_count);
+        assert_eq!(
+            TextureViewDimension::D2Multisample,
+            bntx.nx_header.brti.texture_view_dimension
+        );
+    }
+
+    #[test]
+    fn write_with_progress() {
+        let data = [0u8; (8 * 8 + 4 * 4) * 4 * 2];
+        let bntx = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .mipmap_count(2)
+            .layer_count(2)
+            .build()
+            .unwrap();
+
+        let calls = std::cell::RefCell::new(Vec::new());
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write_with_progress(&mut writer, |written, total| {
+            calls.borrow_mut().push((written, total))
+        })
+        .unwrap();
+
+        let total = bntx.size_on_disk().unwrap() as u64;
+        let calls = calls.into_inner();
+        assert_eq!(writer.into_inner().len() as u64, total);
+        assert!(calls.iter().all(|(_, t)| *t == total));
+        assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(Some(&(total, total)), calls.last());
+
+        // The end of each mipmap for both layers followed by the end of the file.
+        assert_eq!(5, calls.len());
+    }
+
+    #[test]
+    fn builder_flags() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .flags(0)
+            .build()
+            .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bntx = BntxFile::read_le(&mut std::io::Cursor::new(writer.into_inner())).unwrap();
+        assert_eq!(0, bntx.nx_header.brti.flags);
+    }
+
+    #[test]
+    fn write_multiple_dict_entries() {
+        let data = [0u8; 4 * 4 * 4];
+        let mut bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .build()
+            .unwrap();
+
+        let names = ["tex", "tex_nor", "tex_prm"];
+        let mut builder = dict::DictBuilder::new();
+        for name in names {
+            builder.insert(name);
+        }
+        bntx.nx_header.dict = builder.build();
+        bntx.header.inner.str_section.strings =
+            names.iter().map(|n| BntxStr::from(n.to_string())).collect();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+
+        // The memory pool pointer is the same for any number of entries.
+        assert_eq!(
+            MEM_POOL_ADDRESS.to_le_bytes(),
+            bytes[BNTX_HEADER_SIZE + 0x20..BNTX_HEADER_SIZE + 0x28]
+        );
+
+        let new = BntxFile::read_le(&mut std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(MEM_POOL_ADDRESS, new.nx_header.dict_size);
+        assert_eq!(names.len() as u32, new.nx_header.dict.node_count);
+    }
+
+    #[test]
+    fn write_unexpected_str_section_start() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .build()
+            .unwrap();
+
+        // Offsets in the file assume the file starts at the beginning of the writer.
+        let mut writer = std::io::Cursor::new(Vec::new());
+        writer.set_position(8);
+        assert!(matches!(
+            bntx.write(&mut writer),
+            Err(BntxError::UnexpectedStrSectionStart { actual, .. })
+                if actual == START_OF_STR_SECTION as u64 + 8
+        ));
+    }
+
+    fn write_read_format(format: SurfaceFormat, width: u32, height: u32, data: &[u8]) {
+        let bntx = BntxFile::from_image_data("tex", width, height, 1, 1, 1, format, data).unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let new = BntxFile::read_le(&mut std::io::Cursor::new(writer.into_inner())).unwrap();
+
+        assert_eq!(format, new.image_format());
+        assert_eq!(data, new.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn write_read_r16_formats() {
+        let data: Vec<u8> = (0..4 * 4 * 2).map(|i| i as u8).collect();
+        write_read_format(SurfaceFormat::R16Unorm, 4, 4, &data);
+        write_read_format(SurfaceFormat::R16Snorm, 4, 4, &data);
+        write_read_format(SurfaceFormat::R16Sfloat, 4, 4, &data);
+    }
+
+    #[test]
+    fn relocation_section_size() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            3,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4 + 2 * 2 * 4 + 4],
+        )
+        .unwrap();
+
+        let str_size = bntx.header.inner.str_section.get_size();
+        let dict_size = bntx.nx_header.dict.get_size();
+        let table = bntx.compute_relocation_table().unwrap();
+        assert_eq!(0, table.sections[0].position);
+        assert_eq!(
+            size_before_brtd(str_size, dict_size, 3) as u32,
+            table.sections[0].size
+        );
+    }
+
+    fn dds_round_trip_format(format: SurfaceFormat, width: u32, height: u32, data: &[u8]) {
+        let bntx = BntxFile::from_image_data("tex", width, height, 1, 1, 1, format, data).unwrap();
+
+        let dds = create_dds(&bntx).unwrap();
+        let new = create_bntx("tex", &dds).unwrap();
+
+        assert_eq!(format, new.image_format());
+        assert_eq!(data, new.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn dds_round_trip_r16g16_formats() {
+        let data: Vec<u8> = (0..4 * 4 * 4).map(|i| i as u8).collect();
+        for format in [
+            SurfaceFormat::R16G16Unorm,
+            SurfaceFormat::R16G16Snorm,
+            SurfaceFormat::R16G16Sfloat,
+        ] {
+            write_read_format(format, 4, 4, &data);
+            dds_round_trip_format(format, 4, 4, &data);
+        }
+    }
+
+    #[test]
+    fn write_brti_pointer_chain() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .build()
+            .unwrap();
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+
+        let read_u64 = |i: usize| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(value) as usize
+        };
+        let brti_ptr = read_u64(BRTI_PTR_PTR_OFFSET as usize);
+        assert_eq!(HEADER_SIZE + MEM_POOL_SIZE, brti_ptr);
+
+        let brti = read_u64(brti_ptr);
+        assert_eq!(b"BRTI", &bytes[brti..brti + 4]);
+        assert_eq!(
+            START_OF_STR_SECTION
+                + bntx.header.inner.str_section.get_size()
+                + bntx.nx_header.dict.get_size(),
+            brti
+        );
+    }
+
+    #[test]
+    fn write_read_r16g16b16a16_formats() {
+        let data: Vec<u8> = (0..4 * 4 * 4)
+            .flat_map(|i| half::f16::from_f32(i as f32 * 0.25 - 8.0).to_le_bytes())
+            .collect();
+        write_read_format(SurfaceFormat::R16G16B16A16Unorm, 4, 4, &data);
+        write_read_format(SurfaceFormat::R16G16B16A16Snorm, 4, 4, &data);
+        write_read_format(SurfaceFormat::R16G16B16A16Sfloat, 4, 4, &data);
+    }
+
+    #[test]
+    fn write_read_r32_formats() {
+        write_read_format(SurfaceFormat::R32Sfloat, 1, 1, &1.5f32.to_le_bytes());
+        write_read_format(SurfaceFormat::R32Uint, 1, 1, &u32::MAX.to_le_bytes());
+        write_read_format(SurfaceFormat::R32Sint, 1, 1, &(-7i32).to_le_bytes());
+    }
+
+    #[test]
+    fn builder_brtd_offset() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[1u8; 64])
+            .mipmap_count(1)
+            .brtd_offset(0x1FF0)
+            .build()
+            .unwrap();
+        assert_eq!(vec![0x2000], bntx.nx_header.brti.mipmaps.mipmap_offsets);
+
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), bntx.size_on_disk().unwrap());
+        assert_eq!(b"BRTD", &bytes[0x1FF0..0x1FF4]);
+
+        let new = BntxFile::read_le(&mut std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(vec![1u8; 64], new.deswizzled_data().unwrap());
+
+        assert!(matches!(
+            BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[1u8; 64])
+                .brtd_offset(0x100)
+                .build(),
+            Err(BntxError::HeaderTooLarge { max: 0x100, .. })
+        ));
+    }
+
+    #[test]
+    fn write_read_r8g8_formats() {
+        // XY normals with Z reconstructed in the shader.
+        let data: Vec<u8> = (0..4 * 4).flat_map(|i| [i * 16, 255 - i * 16]).collect();
+        write_read_format(SurfaceFormat::R8G8Unorm, 4, 4, &data);
+        write_read_format(SurfaceFormat::R8G8Snorm, 4, 4, &data);
+        dds_round_trip_format(SurfaceFormat::R8G8Snorm, 4, 4, &data);
+    }
+
+    #[test]
+    fn relocation_entry_padding_count() {
+        // The memory pool and BRTI texture pointers are separated by the string and dictionary sections.
+        let brti = 0x220;
+        let pointers = [0x38, 0x40, brti + 0x80, brti + 0x88];
+        let (entry, count) = RelocationEntry::from_pointers(&pointers).unwrap();
+        assert_eq!(4, count);
+        assert_eq!(0x38, entry.position);
+        assert_eq!(2, entry.struct_count);
+        assert_eq!(2, entry.offset_count);
+        assert_eq!(((brti + 0x80 - 0x48) / 8) as u8, entry.padding_count);
+
+        // Groups with different spacing use separate entries.
+        let (entry, count) = RelocationEntry::from_pointers(&[0x28, 0x38, 0x50]).unwrap();
+        assert_eq!(2, count);
+        assert_eq!(1, entry.padding_count);
+    }
+
+    #[test]
+    fn dds_round_trip_r10g10b10a2() {
+        // Packed 10-bit RGB and 2-bit alpha values.
+        let data: Vec<u8> = (0..4 * 4u32)
+            .flat_map(|i| (i << 20 | i << 10 | i | 3 << 30).to_le_bytes())
+            .collect();
+        write_read_format(SurfaceFormat::R10G10B10A2Unorm, 4, 4, &data);
+        dds_round_trip_format(SurfaceFormat::R10G10B10A2Unorm, 4, 4, &data);
+
+        let bntx =
+            BntxFile::from_image_data("tex", 4, 4, 1, 1, 1, SurfaceFormat::R10G10B10A2Unorm, &data)
+                .unwrap();
+        let dds = create_dds(&bntx).unwrap();
+        assert_eq!(
+            Some(ddsfile::DxgiFormat::R10G10B10A2_UNorm),
+            dds.get_dxgi_format()
+        );
+        assert!(!dds.header.caps2.contains(ddsfile::Caps2::VOLUME));
+    }
+
+    #[test]
+    fn format_conversion_matrix() {
+        use formats::ConversionSupport;
+
+        let matrix = BntxFile::format_conversion_matrix();
+        let count = formats::COMPRESSED_FORMATS.len() + formats::UNCOMPRESSED_FORMATS.len();
+        assert_eq!(count * count, matrix.len());
+
+        let support = |from, to| matrix[&(from, to)];
+        assert_eq!(
+            ConversionSupport::Lossless,
+            support(SurfaceFormat::BC7Unorm, SurfaceFormat::BC7Srgb)
+        );
+        assert_eq!(
+            ConversionSupport::Lossless,
+            support(SurfaceFormat::R8Unorm, SurfaceFormat::R8G8B8A8Unorm)
+        );
+        assert_eq!(
+            ConversionSupport::Lossy,
+            support(
+                SurfaceFormat::R16G16B16A16Sfloat,
+                SurfaceFormat::R8G8B8A8Unorm
+            )
+        );
+        assert_eq!(
+            ConversionSupport::Lossy,
+            support(
+                SurfaceFormat::R10G10B10A2Unorm,
+                SurfaceFormat::R8G8B8A8Unorm
+            )
+        );
+        assert_eq!(
+            ConversionSupport::Lossless,
+            support(SurfaceFormat::R8Unorm, SurfaceFormat::R16Sfloat)
+        );
+        assert_eq!(
+            ConversionSupport::Lossless,
+            support(SurfaceFormat::R16Sfloat, SurfaceFormat::R32Sfloat)
+        );
+
+        // Equal bit widths can still lose range, signs, or precision.
+        for (from, to) in [
+            (SurfaceFormat::R16Unorm, SurfaceFormat::R16Snorm),
+            (SurfaceFormat::R16Unorm, SurfaceFormat::R16Sfloat),
+            (SurfaceFormat::R32Uint, SurfaceFormat::R32Sint),
+            (SurfaceFormat::R32Uint, SurfaceFormat::R32Sfloat),
+        ] {
+            assert_eq!(
+                ConversionSupport::Lossy,
+                support(from, to),
+                "{from:?} {to:?}"
+            );
+        }
+
+        assert_eq!(
+            ConversionSupport::RequiresDecoder,
+            support(SurfaceFormat::BC1Unorm, SurfaceFormat::R8G8B8A8Unorm)
+        );
+        assert_eq!(
+            ConversionSupport::Unsupported,
+            support(SurfaceFormat::R8G8B8A8Unorm, SurfaceFormat::BC7Unorm)
+        );
+    }
+
+    #[test]
+    fn write_read_astc_formats() {
+        assert_eq!(
+            (6, 6),
+            (
+                SurfaceFormat::Astc6x6Unorm.block_dim().width.get(),
+                SurfaceFormat::Astc6x6Unorm.block_dim().height.get()
+            )
+        );
+
+        // Each ASTC block is 16 bytes regardless of the block dimensions.
+        // Partial blocks at the edges still take up a full block.
+        let data: Vec<u8> = (0..2 * 2 * 16).map(|i| i as u8).collect();
+        write_read_format(SurfaceFormat::Astc4x4Unorm, 8, 8, &data);
+        write_read_format(SurfaceFormat::Astc4x4Srgb, 8, 8, &data);
+        write_read_format(SurfaceFormat::Astc6x6Unorm, 10, 12, &data);
+        write_read_format(SurfaceFormat::Astc6x6Srgb, 12, 12, &data);
+        write_read_format(SurfaceFormat::Astc8x8Unorm, 16, 16, &data);
+        write_read_format(SurfaceFormat::Astc8x8Srgb, 9, 16, &data);
+    }
+
+    #[test]
+    fn bytes_per_pixel_is_bytes_per_block() {
+        assert_eq!(4, SurfaceFormat::R8G8B8A8Unorm.bytes_per_pixel());
+        assert_eq!(8, SurfaceFormat::BC1Unorm.bytes_per_pixel());
+        assert_eq!(16, SurfaceFormat::BC7Unorm.bytes_per_pixel());
+        assert_eq!(16, SurfaceFormat::Astc8x8Unorm.bytes_per_pixel());
+
+        // An 8x8 BC1 surface has 2x2 blocks of 8 bytes each.
+        assert_eq!(
+            2 * 2 * 8,
+            unswizzled_surface_size(8, 8, 1, SurfaceFormat::BC1Unorm, 1, 1)
+        );
+    }
+
+    #[test]
+    fn parse_etc2_eac_formats() {
+        let data: Vec<u8> = (0..2 * 2 * 8).map(|i| i as u8).collect();
+        write_read_format(SurfaceFormat::Etc2Rgb8Unorm, 8, 8, &data);
+
+        let data: Vec<u8> = (0..2 * 2 * 16).map(|i| i as u8).collect();
+        write_read_format(SurfaceFormat::EacR11Unorm, 8, 8, &data);
+
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            8,
+            8,
+            1,
+            1,
+            1,
+            SurfaceFormat::Etc2Rgb8Unorm,
+            &[0u8; 2 * 2 * 8],
+        )
+        .unwrap();
+        assert!(matches!(
+            create_dds(&bntx),
+            Err(BntxError::UnsupportedOperation {
+                format: SurfaceFormat::Etc2Rgb8Unorm,
+                ..
+            })
+        ));
+
+        assert_eq!(None, SurfaceFormat::Etc2Rgb8Unorm.try_into_dxgi());
+        assert_eq!(None, SurfaceFormat::EacR11Unorm.try_into_dxgi());
+        assert_eq!(
+            Some(ddsfile::DxgiFormat::BC7_UNorm),
+            SurfaceFormat::BC7Unorm.try_into_dxgi()
+        );
+    }
+
+    #[test]
+    fn read_relocation_table_entries() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            3,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4 + 2 * 2 * 4 + 4],
+        )
+        .unwrap();
+        let bytes = bntx.to_bytes().unwrap();
+        let mut bntx = BntxFile::read_le(&mut std::io::Cursor::new(bytes)).unwrap();
+
+        let entries = bntx.read_relocation_table_entries();
+        assert!(entries.contains(&(0x28, "NX header BRTI pointer")));
+        assert!(entries.contains(&(0x30, "NX header BRTD pointer")));
+        assert!(entries.contains(&(0x198, "BRTI pointer")));
+        assert_eq!(
+            3,
+            entries
+                .iter()
+                .filter(|(_, d)| *d == "mipmap offset")
+                .count()
+        );
+        assert!(entries.iter().all(|(_, d)| *d != "unknown pointer"));
+
+        let table = bntx.compute_relocation_table().unwrap();
+        let pointer_count: usize = table
+            .entries
+            .iter()
+            .map(|e| e.struct_count as usize * e.offset_count as usize)
+            .sum();
+        assert_eq!(pointer_count, entries.len());
+
+        // Only the stored table is described even if it differs from the computed table.
+        bntx.header.inner.reloc_table.entries.truncate(1);
+        assert_eq!(
+            vec![(0x28, "NX header BRTI pointer"), (0x198, "BRTI pointer")],
+            bntx.read_relocation_table_entries()
+        );
     }
 
-    fn block_dim(&self) -> BlockDim {
-        match self {
-            SurfaceFormat::R8Unorm => BlockDim::uncompressed(),
-            SurfaceFormat::R8G8B8A8Unorm => BlockDim::uncompressed(),
-            SurfaceFormat::R8G8B8A8Srgb => BlockDim::uncompressed(),
-            SurfaceFormat::B8G8R8A8Unorm => BlockDim::uncompressed(),
-            SurfaceFormat::B8G8R8A8Srgb => BlockDim::uncompressed(),
-            SurfaceFormat::BC1Unorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC1Srgb => BlockDim::block_4x4(),
-            SurfaceFormat::BC2Unorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC2Srgb => BlockDim::block_4x4(),
-            SurfaceFormat::BC3Unorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC3Srgb => BlockDim::block_4x4(),
-            SurfaceFormat::BC4Unorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC4Snorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC5Unorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC5Snorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC6Sfloat => BlockDim::block_4x4(),
-            SurfaceFormat::BC6Ufloat => BlockDim::block_4x4(),
-            SurfaceFormat::BC7Unorm => BlockDim::block_4x4(),
-            SurfaceFormat::BC7Srgb => BlockDim::block_4x4(),
-        }
+    #[test]
+    fn read_invalid_magic() {
+        let result = BntxFile::read_le(&mut std::io::Cursor::new(b"DDS \0\0\0\0".to_vec()));
+        assert!(matches!(
+            result,
+            Err(BntxError::InvalidMagic { expected, got }) if expected == b"BNTX" && got == b"DDS "
+        ));
+
+        let result = BntxFile::read_le(&mut std::io::Cursor::new(b"BN".to_vec()));
+        assert!(matches!(result, Err(BntxError::InvalidMagic { got, .. }) if got == b"BN"));
     }
-}
 
-#[derive(BinRead, Debug)]
-#[br(magic = b"BRTI")]
-struct Brti {
-    size: u32,  // offset?
-    size2: u64, // size?
-    flags: u8,
-    texture_dimension: TextureDimension,
-    tile_mode: u16,
-    swizzle: u16,
-    mipmap_count: u16,
-    multi_sample_count: u32,
-    format: SurfaceFormat,
-    unk2: u32,
-    width: u32,
-    height: u32,
-    depth: u32,
-    layer_count: u32,
-    block_height_log2: u32,
-    unk4: [u32; 6],  // TODO: What is this?
-    image_size: u32, // the total size of all layers and mipmaps with padding
-    align: u32,      // usually 512 to match the expected mipmap alignment for swizzled surfaces.
-    comp_sel: u32,
-    texture_view_dimension: TextureViewDimension,
+    #[test]
+    fn read_unsupported_format() {
+        let bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .build()
+            .unwrap();
 
-    #[br(parse_with = FilePtr64::parse)]
-    name_addr: BntxStr,
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+        let mut bytes = writer.into_inner();
 
-    parent_addr: u64, // pointer to nx header
+        // The format follows the BRTI magic, sizes, and other header fields.
+        let brti = START_OF_STR_SECTION
+            + bntx.header.inner.str_section.get_size()
+            + bntx.nx_header.dict.get_size();
+        bytes[brti + 0x1C..brti + 0x20].copy_from_slice(&0xFFFFu32.to_le_bytes());
 
-    // TODO: This is a pointer to an array of u64 mipmap offsets.
-    // TODO: Parse the entire surface in one vec but store the mipmap offsets?
-    #[br(parse_with = FilePtr64::parse, args { inner: (mipmap_count,)})]
-    mipmaps: Mipmaps, // u64 pointer to mipmap offsets?
-    unk5: u64, // padding?
-    unk6: u64, // offset?
-    unk7: u64, // offset?
-}
+        let result = BntxFile::read_le(&mut std::io::Cursor::new(bytes));
+        assert!(matches!(result, Err(BntxError::UnsupportedFormat(0xFFFF))));
 
-#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq)]
-#[brw(repr(u8))]
-pub enum TextureDimension {
-    D1 = 1,
-    D2 = 2,
-    D3 = 3,
-}
+        assert_eq!(
+            SurfaceFormat::BC7Srgb,
+            SurfaceFormat::try_from(0x2006).unwrap()
+        );
+    }
 
-#[derive(Debug, BinRead, BinWrite, Clone, Copy, PartialEq, Eq)]
-#[brw(repr(u32))]
-pub enum TextureViewDimension {
-    D1 = 0,
-    D2 = 1,
-    D3 = 2,
-    Cube = 3,
-    // TODO: Fill in other known variants
-}
+    #[test]
+    fn from_bytes_to_bytes_round_trip() {
+        let data: Vec<u8> = (0..8 * 8 * 4 + 4 * 4 * 4).map(|i| i as u8).collect();
+        let bntx = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::R8G8B8A8Srgb, &data)
+            .mipmap_count(2)
+            .build()
+            .unwrap();
 
-const SIZE_OF_BRTI: usize = 0xA0;
+        let bytes = bntx.to_bytes().unwrap();
+        let new = BntxFile::from_bytes(&bytes).unwrap();
+        assert_eq!("tex", new.header.inner.file_name.as_str());
+        assert_eq!((8, 8, 1), (new.width(), new.height(), new.depth()));
+        assert_eq!(2, new.num_mipmaps());
+        assert_eq!(SurfaceFormat::R8G8B8A8Srgb, new.image_format());
+        assert_eq!(data, new.deswizzled_data().unwrap());
+        assert_eq!(bytes, new.to_bytes().unwrap());
 
-impl Brti {
-    fn write_options<W: io::Write + io::Seek>(
-        &self,
-        writer: &mut W,
-        endian: binrw::Endian,
-        parent: &BntxFile,
-    ) -> Result<(), binrw::error::Error> {
-        (
-            (
-                b"BRTI",
-                self.size,
-                self.size2,
-                self.flags,
-                self.texture_dimension,
-                self.tile_mode,
-                self.swizzle,
-                self.mipmap_count,
-                self.multi_sample_count,
-                &self.format,
-                self.unk2,
-                self.width,
-                self.height,
-                self.depth,
-                self.layer_count,
-                self.block_height_log2,
-                self.unk4,
-                self.image_size,
-                self.align,
-                self.comp_sel,
-            ),
-            self.texture_view_dimension,
-            FILENAME_STR_OFFSET as u64,
-            BNTX_HEADER_SIZE as u64,
-            (START_OF_STR_SECTION
-                + parent.header.inner.str_section.get_size()
-                + parent.nx_header.dict.get_size()
-                + SIZE_OF_BRTI
-                + 0x200) as u64,
-            0u64,
-            (START_OF_STR_SECTION
-                + parent.header.inner.str_section.get_size()
-                + parent.nx_header.dict.get_size()
-                + SIZE_OF_BRTI) as u64,
-            (START_OF_STR_SECTION
-                + parent.header.inner.str_section.get_size()
-                + parent.nx_header.dict.get_size()
-                + SIZE_OF_BRTI
-                + 0x100) as u64,
-            0u64,
-            0u64,
-        )
-            .write_options(writer, endian, ())
+        assert!(matches!(
+            BntxFile::from_bytes(&bytes[4..]),
+            Err(BntxError::InvalidMagic { .. })
+        ));
     }
-}
 
-#[binrw]
-#[brw(magic = b"BRTD")]
-struct Brtd {
-    // Size of the image data + BRTD header.
-    #[brw(pad_before = 4)]
-    #[br(temp)]
-    #[bw(calc = image_data.len() as u64 + 16)]
-    brtd_size: u64,
+    #[test]
+    fn write_read_all_formats() {
+        let formats: Vec<_> = formats::UNCOMPRESSED_FORMATS
+            .iter()
+            .chain(formats::COMPRESSED_FORMATS)
+            .copied()
+            .collect();
 
-    #[br(count = brtd_size - 16)]
-    image_data: Vec<u8>,
-}
+        for format in formats {
+            let size = unswizzled_surface_size(16, 16, 1, format, 3, 1);
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let bntx = BntxFileBuilder::new("tex", 16, 16, format, &data)
+                .mipmap_count(3)
+                .build()
+                .unwrap();
 
-impl fmt::Debug for Brtd {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "ImageData[{:?}]", self.image_data.len())
+            let bytes = bntx.to_bytes().unwrap();
+            let mut new = BntxFile::from_bytes(&bytes).unwrap();
+
+            // The last two BRTI pointers are calculated when writing.
+            new.nx_header.brti.unk6 = 0;
+            new.nx_header.brti.unk7 = 0;
+
+            // The Debug output includes every header field and the swizzled data.
+            assert_eq!(format!("{bntx:?}"), format!("{new:?}"), "{format:?}");
+            assert_eq!(data, new.deswizzled_data().unwrap(), "{format:?}");
+            assert_eq!(bytes, new.to_bytes().unwrap(), "{format:?}");
+        }
     }
-}
 
-use binrw::io::{Read, Seek};
+    #[test]
+    fn set_swizzled_data_invalid_layout() {
+        let data: Vec<u8> = (0..8 * 8 * 4 + 4 * 4 * 4).map(|i| i as u8).collect();
+        let mut bntx =
+            BntxFile::from_image_data("tex", 8, 8, 1, 2, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+        bntx.nx_header.brti.mipmaps.mipmap_offsets.reverse();
 
-fn read_double_indirect<'a, T: BinRead, R: Read + Seek>(
-    reader: &mut R,
-    endian: binrw::Endian,
-    args: T::Args<'a>,
-) -> BinResult<T> {
-    let offset1 = <u64>::read_options(reader, endian, ())?;
-    let position = reader.stream_position()?;
+        let swizzled = bntx.nx_header.brtd.image_data.clone();
+        let reloc_table = format!("{:?}", bntx.header.inner.reloc_table);
+        assert!(bntx.set_swizzled_data(vec![0u8; swizzled.len()]).is_err());
+        assert_eq!(swizzled, bntx.nx_header.brtd.image_data);
+        assert_eq!(reloc_table, format!("{:?}", bntx.header.inner.reloc_table));
+    }
 
-    reader.seek(SeekFrom::Start(offset1))?;
-    let offset2 = <u64>::read_options(reader, endian, ())?;
+    #[test]
+    fn size_on_disk_invalid_layout() {
+        let data = [0u8; 8 * 8 * 4 + 4 * 4 * 4];
+        let mut bntx =
+            BntxFile::from_image_data("tex", 8, 8, 1, 2, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+        bntx.nx_header.brti.mipmaps.mipmap_offsets.reverse();
+        assert!(bntx.size_on_disk().is_err());
+    }
 
-    reader.seek(SeekFrom::Start(offset2))?;
-    let value = T::read_options(reader, endian, args)?;
+    #[test]
+    fn write_header_overlaps_brtd() {
+        let mut bntx = BntxFileBuilder::new("tex", 4, 4, SurfaceFormat::R8G8B8A8Unorm, &[0u8; 64])
+            .build()
+            .unwrap();
+        bntx.nx_header.brti.mipmaps.mipmap_offsets = vec![0x200];
 
-    reader.seek(SeekFrom::Start(position))?;
-    Ok(value)
-}
+        let mut writer = std::io::Cursor::new(Vec::new());
+        assert!(matches!(
+            bntx.write(&mut writer),
+            Err(BntxError::HeaderTooLarge { max: 0x1F0, .. })
+        ));
+    }
 
-#[derive(BinRead, Debug)]
-#[br(import(mipmap_count: u16))]
-struct Mipmaps {
-    #[br(count = mipmap_count)]
-    mipmap_offsets: Vec<u64>,
-}
+    #[test]
+    fn relocation_table_offset_overflow() {
+        let offset = u32::MAX as u64 + 1;
+        assert!(matches!(
+            RelocationTable::from_pointers(&[(0, offset, vec![vec![0]])]),
+            Err(BntxError::OffsetOverflow { .. })
+        ));
+        assert!(matches!(
+            RelocationEntry::from_pointers(&[offset]),
+            Err(BntxError::OffsetOverflow { .. })
+        ));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn read_mipmap_from_reader_trimmed() {
+        let mut data = vec![0u8; (8 * 8 + 4 * 4) * 4];
+        data[0] = 1;
+        let bntx = BntxFileBuilder::new("tex", 8, 8, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .mipmap_count(2)
+            .trim_trailing_zeros(true)
+            .build()
+            .unwrap();
 
-    use crate::dds::create_bntx;
-    use crate::dds::create_dds;
-    use std::io::BufWriter;
+        let mut writer = std::io::Cursor::new(Vec::new());
+        bntx.write(&mut writer).unwrap();
+
+        assert_eq!(
+            &data[..8 * 8 * 4],
+            &bntx.read_mipmap_from_reader(&mut writer, 0, 0).unwrap()[..]
+        );
+        assert_eq!(
+            &data[8 * 8 * 4..],
+            &bntx.read_mipmap_from_reader(&mut writer, 0, 1).unwrap()[..]
+        );
+    }
 
     #[test]
-    fn try_parse() {
-        let original = BntxFile::from_file("chara_1_mario_00.bntx").unwrap();
-        original.write_to_file("chara_1_mario_00.out.bntx").unwrap();
+    fn copy_header_from_trimmed() {
+        let mut data = vec![0u8; 8 * 8 * 4];
+        data[0] = 1;
+        let mut bntx = BntxFileBuilder::new("a", 8, 8, SurfaceFormat::R8G8B8A8Unorm, &data)
+            .trim_trailing_zeros(true)
+            .build()
+            .unwrap();
+        let template = BntxFile::from_image_data(
+            "template",
+            8,
+            8,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Srgb,
+            &[0u8; 8 * 8 * 4],
+        )
+        .unwrap();
 
-        let dds = create_dds(&original).unwrap();
-        let mut writer = BufWriter::new(std::fs::File::create("chara_1_mario_00.dds").unwrap());
-        dds.write(&mut writer).unwrap();
+        bntx.copy_header_from(&template).unwrap();
+        assert_eq!(SurfaceFormat::R8G8B8A8Srgb, bntx.image_format());
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+    }
 
-        create_bntx("chara_1_mario_00", &dds)
+    #[test]
+    fn write_to_file_atomic_concurrent() {
+        let path = temp_path("write_to_file_atomic_concurrent", "bntx");
+
+        let threads: Vec<_> = [1u8, 2u8]
+            .iter()
+            .map(|&value| {
+                let bntx = BntxFile::from_image_data(
+                    "tex",
+                    64,
+                    64,
+                    1,
+                    1,
+                    1,
+                    SurfaceFormat::R8G8B8A8Unorm,
+                    &vec![value; 64 * 64 * 4],
+                )
+                .unwrap();
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        bntx.write_to_file_atomic(&path).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(temp_files(&path).is_empty());
+
+        let data = BntxFile::from_file(&path)
             .unwrap()
-            .write_to_file("chara_1_mario_00.dds.bntx")
+            .deswizzled_data()
             .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(data == vec![1u8; 64 * 64 * 4] || data == vec![2u8; 64 * 64 * 4]);
+    }
+
+    #[test]
+    fn write_relocation_table_reference() {
+        let bntx = BntxFile::from_image_data(
+            "tex",
+            4,
+            4,
+            1,
+            1,
+            1,
+            SurfaceFormat::R8G8B8A8Unorm,
+            &[0u8; 4 * 4 * 4],
+        )
+        .unwrap();
+        let bytes = bntx.to_bytes().unwrap();
+
+        // The known-good _RLT section for a single 4x4 texture named "tex".
+        let expected = [
+            0x5F, 0x52, 0x4C, 0x54, 0x00, 0x12, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x90, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x0F, 0x00, 0x00, 0x10, 0x02, 0x00, 0x00,
+            0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x02, 0x00,
+            0x01, 0x2D, 0x38, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x44, 0xD0, 0x01, 0x00, 0x00,
+            0x02, 0x00, 0x01, 0x01, 0x48, 0x02, 0x00, 0x00, 0x01, 0x00, 0x03, 0x00, 0x30, 0x00,
+            0x00, 0x00, 0x02, 0x00, 0x01, 0x8A,
+        ];
+        assert_eq!(0x1268, bytes.len());
+        assert_eq!(&expected[..], &bytes[0x1200..]);
+    }
+
+    #[test]
+    fn bntx_file_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BntxFile>();
+    }
+
+    #[test]
+    fn deswizzled_data_ref_cached() {
+        let data: Vec<u8> = (0..4 * 4 * 4).map(|i| i as u8).collect();
+        let bntx =
+            BntxFile::from_image_data("tex", 4, 4, 1, 1, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+
+        let first = bntx.deswizzled_data_ref().unwrap();
+        let second = bntx.deswizzled_data_ref().unwrap();
+        assert_eq!(&data[..], first);
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert_eq!(data, bntx.deswizzled_data().unwrap());
+    }
+
+    #[test]
+    fn copy_header_from_invalid_layout() {
+        let data = [1u8; 4 * 4 * 4 + 2 * 2 * 4 + 4];
+        let mut bntx =
+            BntxFile::from_image_data("a", 4, 4, 1, 3, 1, SurfaceFormat::R8G8B8A8Unorm, &data)
+                .unwrap();
+        let mut template = BntxFile::from_image_data(
+            "template",
+            4,
+            4,
+            1,
+            3,
+            1,
+            SurfaceFormat::R8G8B8A8Srgb,
+            &[0u8; 4 * 4 * 4 + 2 * 2 * 4 + 4],
+        )
+        .unwrap();
+        template.nx_header.brti.mipmaps.mipmap_offsets.swap(1, 2);
+
+        let header = format!("{bntx:?}");
+        assert!(matches!(
+            bntx.copy_header_from(&template),
+            Err(BntxError::UnorderedMipmapOffsets { .. })
+        ));
+        assert_eq!(header, format!("{bntx:?}"));
+        assert_eq!(data.to_vec(), bntx.deswizzled_data().unwrap());
     }
 }
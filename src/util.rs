@@ -0,0 +1,18 @@
+//! Helper functions for calculating BNTX layouts.
+
+/// Rounds `x` up to the next multiple of `n`.
+/// The alignment `n` must be a power of two.
+///
+/// # Examples
+/**
+```rust
+use bntx::util::align;
+
+assert_eq!(0, align(0, 8));
+assert_eq!(8, align(5, 8));
+assert_eq!(512, align(512, 512));
+```
+ */
+pub fn align(x: usize, n: usize) -> usize {
+    (x + n - 1) & !(n - 1)
+}